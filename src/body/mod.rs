@@ -13,8 +13,17 @@ use std::fmt;
 
 
 pub const PL_DENSITY: f64 = 5000.0;
+pub const DEFAULT_COLOUR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+// Used for auto naming. After running out, goes round again with a number on the end (Alpha 2 etc).
+const AUTO_NAMES: [&str; 24] = [
+    "Alpha", "Beta", "Gamma", "Delta", "Epsilon", "Zeta", "Eta", "Theta",
+    "Iota", "Kappa", "Lambda", "Mu", "Nu", "Xi", "Omicron", "Pi",
+    "Rho", "Sigma", "Tau", "Upsilon", "Phi", "Chi", "Psi", "Omega",
+];
 
 // For mobile objects
+#[allow(dead_code)]
 pub trait Mobile<T: RealField> {
     fn pos(&self) -> &Point2<T>;
     fn pos_mut(&mut self) -> &mut Point2<T>;
@@ -32,18 +41,23 @@ pub type BodyID = u32;
 #[derive(Clone)]
 pub struct Body {
     pub id: BodyID,
+    pub name: Option<String>,
     pub pos: Point2<f64>,
+    pub prev_pos: Point2<f64>,  // Position before the last step, for interpolating between steps when drawing.
     vel: Vector2<f64>,
     pub radius: f64,
     pub mass: f64,
-    pub res_force: Vector2<f64>,
+    pub colour: [f32; 4],   // RGBA
+    pub softening: Option<f64>,     // Plummer softening length. None uses the world's.
 }
 
 impl Body {
     pub fn new(id: BodyID, pos: Point2<f64>, vel: Vector2<f64>, radius: f64, m: f64) -> Body {
         Body {
             id,
+            name: None,
             pos,
+            prev_pos: pos,
             vel,
             radius,
             mass: if m <= 0.0 {
@@ -51,34 +65,74 @@ impl Body {
             } else {
                 m
             },
-            res_force: Vector2::new(0.0, 0.0),
+            colour: DEFAULT_COLOUR,
+            softening: None,
+        }
+    }
+
+    // Unique name for an id.
+    pub fn auto_name(id: BodyID) -> String {
+        let round = id as usize / AUTO_NAMES.len();
+        let name = AUTO_NAMES[id as usize % AUTO_NAMES.len()];
+
+        if round == 0 {
+            name.to_owned()
+        } else {
+            format!("{} {}", name, round + 1)
+        }
+    }
+
+    // Name if it has one, otherwise the id.
+    pub fn label(&self) -> String {
+        match self.name {
+            Some(ref name) => format!("{} (id {})", name, self.id),
+            None => format!("id {}", self.id),
         }
     }
 
-    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+    // alpha is how far between the previous and current position to draw the body (0 - 1).
+    pub fn draw(&self, ctx: &mut Context, alpha: f64) -> GameResult {
         let circ = Mesh::new_circle(
             ctx,
             DrawMode::fill(),
             Point2::new(0.0, 0.0),
             self.radius as f32,
             0.05,
-            graphics::WHITE,
+            self.colour.into(),
         )?;
 
         graphics::draw(
             ctx,
             &circ,
-            DrawParam::default().dest(cast_point2_to_f32!(self.pos)),
+            DrawParam::default().dest(cast_point2_to_f32!(self.interpolated_pos(alpha))),
+        )?;
+
+        Ok(())
+    }
+
+    // Ring around the body, for showing that it is selected. gap and width are in world units.
+    pub fn draw_outline(&self, ctx: &mut Context, alpha: f64, gap: f32, width: f32) -> GameResult {
+        let ring = Mesh::new_circle(
+            ctx,
+            DrawMode::stroke(width),
+            Point2::new(0.0, 0.0),
+            self.radius as f32 + gap,
+            0.05,
+            [1.0, 0.85, 0.2, 1.0].into(),
+        )?;
+
+        graphics::draw(
+            ctx,
+            &ring,
+            DrawParam::default().dest(cast_point2_to_f32!(self.interpolated_pos(alpha))),
         )?;
 
         Ok(())
     }
 
-    pub fn update_physics(&mut self, dt: f64) {
-        // F/m = a
-        self.vel += (self.res_force / self.mass) * dt;
-        self.pos += self.vel * dt;
-        self.res_force = Vector2::new(0.0, 0.0);
+    #[inline]
+    pub fn interpolated_pos(&self, alpha: f64) -> Point2<f64> {
+        self.prev_pos + (self.pos - self.prev_pos) * alpha
     }
 
     #[inline]
@@ -99,6 +153,7 @@ impl Body {
     }
 
     #[inline]
+    #[allow(dead_code)]
     fn get_radius_from_mass(mass: f64) -> f64 {
         Self::inverse_volume(mass/PL_DENSITY)
     }
@@ -108,6 +163,11 @@ impl Body {
         self.vel * self.mass
     }
 
+    #[inline]
+    pub fn kinetic_energy(&self) -> f64 {
+        0.5 * self.mass * self.vel.norm_squared()
+    }
+
     pub fn collide(&mut self, other: &Self) {
         let total_momentum = self.get_momentum() + other.get_momentum();
         let total_mass = self.mass + other.mass;
@@ -133,6 +193,7 @@ impl Body {
     }
 
     // ratio is percentage of planet to keep.
+    #[allow(dead_code)]
     pub fn split(&mut self, ratio: f64, new_id: BodyID, split_momentum: Vector2<f64>, split_angle: f64) -> Body {
         let my_new_mass = self.mass * ratio;
         let new_pl_mass = self.mass - my_new_mass;
@@ -147,7 +208,7 @@ impl Body {
             // let dr = old_radius - new_pl_radius;
             // tools::get_components(dr, split_angle);
             let mag = self.radius + new_pl_radius + 2.0;    // Don't touch otherwise will cause chain reaction
-            (self.pos + tools::get_components(mag, split_angle)).into()
+            self.pos + tools::get_components(mag, split_angle)
         };
 
         self.vel -= split_momentum/self.mass;
@@ -178,45 +239,68 @@ impl fmt::Debug for Body {
 
 impl From<&BodySaveData> for Body {
     fn from(pl_save: &BodySaveData) -> Self {
-        Body {
-            id: pl_save.id,
-            pos: Point2::new(pl_save.pos_x, pl_save.pos_y),
-            vel: Vector2::new(pl_save.vel_x, pl_save.vel_y),
-            radius: pl_save.radius,
-            mass: pl_save.mass,
-            res_force: Vector2::new(0.0, 0.0),
-        }
+        let mut pl = Body::new(
+            pl_save.id,
+            Point2::new(pl_save.pos_x, pl_save.pos_y),
+            Vector2::new(pl_save.vel_x, pl_save.vel_y),
+            pl_save.radius,
+            pl_save.mass,   // If left out of a scenario file, worked out from the radius
+        );
+        pl.name = pl_save.name.clone();
+        pl.colour = pl_save.colour;
+        pl.softening = pl_save.softening;
+        pl
     }
 }
 
 
+#[allow(dead_code)]
 pub enum BodyType {
     Planet,
     Star,
 }
 
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+// Defaults are for hand written scenario files, they make no difference to bincode.
+#[derive(Serialize, Deserialize, Clone)]
 pub struct BodySaveData {
+    #[serde(default)]
     pub id: BodyID,
+    #[serde(default)]
+    pub name: Option<String>,
     pub pos_x: f64,
     pub pos_y: f64,
+    #[serde(default)]
     pub vel_x: f64,
+    #[serde(default)]
     pub vel_y: f64,
     pub radius: f64,
-    pub mass: f64
+    #[serde(default)]
+    pub mass: f64,
+    #[serde(default = "default_colour")]
+    pub colour: [f32; 4],
+    #[serde(default)]
+    pub softening: Option<f64>,
+}
+
+#[inline]
+fn default_colour() -> [f32; 4] {
+    DEFAULT_COLOUR
 }
 
 impl From<std::cell::Ref<'_, Body>> for BodySaveData {
     fn from(pl: std::cell::Ref<'_, Body>) -> Self {
         BodySaveData {
             id: pl.id,
+            name: pl.name.clone(),
             pos_x: pl.pos.x,
             pos_y: pl.pos.y,
             vel_x: pl.vel.x,
             vel_y: pl.vel.y,
             radius: pl.radius,
             mass: pl.mass,
+            colour: pl.colour,
+            softening: pl.softening,
         }
     }
 }
\ No newline at end of file
@@ -3,9 +3,14 @@ use na::Point2;
 use ggez::{GameResult};
 use ggez::graphics::spritebatch;
 
+use serde::{Serialize, Deserialize};
+
 use std::time::Duration;
 
-use crate::particles::{planet_particles::PlanetTrailParticleSys, ParticleSystem};
+use crate::particles::{
+    planet_particles::{PlanetTrailParticleSys, TrailParticleSaveData},
+    ParticleSystem,
+};
 
 // const TRAIL_PLACEMENT_PERIOD: f64 = 0.05;
 // const TRAIL_NODE_LIFETIME: Duration = Duration::from_millis(1000);
@@ -20,16 +25,34 @@ pub struct PlanetTrail {
 }
 
 impl PlanetTrail {
-    pub fn new(pos: Point2<f32>) -> PlanetTrail {
-        let p = PlanetTrail {
+    pub fn new(pos: Point2<f32>, seed: u64) -> PlanetTrail {
+        PlanetTrail {
             pos,
-            particles: PlanetTrailParticleSys::new(),
+            particles: PlanetTrailParticleSys::new(seed),
             parent_dead: false,
             // linear_trail: VecDeque::with_capacity(40),
             // linear_node_placement_timer: 0.0,
-        };
+        }
+    }
 
-        p
+    pub fn from_save_data(save: &PlanetTrailSaveData) -> PlanetTrail {
+        PlanetTrail {
+            pos: Point2::new(save.pos_x, save.pos_y),
+            particles: PlanetTrailParticleSys::from_save_data(save.emmision_timer, save.seed, save.emitted, &save.particles),
+            parent_dead: save.parent_dead,
+        }
+    }
+
+    pub fn save_data(&self) -> PlanetTrailSaveData {
+        PlanetTrailSaveData {
+            pos_x: self.pos.x,
+            pos_y: self.pos.y,
+            parent_dead: self.parent_dead,
+            emmision_timer: self.particles.emmision_timer(),
+            seed: self.particles.seed(),
+            emitted: self.particles.emitted(),
+            particles: self.particles.particle_save_data(),
+        }
     }
 
     pub fn update(&mut self, dt: f64, current_time: &Duration) {
@@ -127,6 +150,17 @@ impl PlanetTrail {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlanetTrailSaveData {
+    pos_x: f32,
+    pos_y: f32,
+    parent_dead: bool,
+    emmision_timer: f64,
+    seed: u64,
+    emitted: u64,   // So the particles carry on from where the save left off
+    particles: Vec<TrailParticleSaveData>,
+}
+
 // #[derive(Debug)]
 // struct TrailNode {
 //     pos: Point2<f32>,
@@ -0,0 +1,137 @@
+use ggez::graphics::Rect;
+use ggez::nalgebra as na;
+use na::{Point2, Vector2};
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    body::BodyID,
+    world::{self, Merge, World},
+};
+
+pub const ZOOM_LIMITS: (f64, f64) = (0.01, 50.0);
+pub const ZOOM_STEP: f64 = 1.1;        // Zoom multiplier per scroll wheel notch
+pub const KEY_PAN_SPEED: f64 = 600.0;  // Screen pixels per second
+pub const PICK_RADIUS: f64 = 6.0;      // Screen pixels. Minimum radius when clicking on bodies.
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Follow {
+    Free,
+    Body(BodyID),
+    CentreOfMass,
+}
+
+pub struct Camera {
+    pub pos: Point2<f64>,   // World position at the centre of the screen
+    pub zoom: f64,          // Screen pixels per world unit
+    pub follow: Follow,
+    screen_size: Vector2<f64>,
+}
+
+impl Camera {
+    // Starts off with world coordinates lined up with the screen.
+    pub fn new(screen_w: f32, screen_h: f32) -> Camera {
+        Camera {
+            pos: Point2::new(screen_w as f64 / 2.0, screen_h as f64 / 2.0),
+            zoom: 1.0,
+            follow: Follow::Free,
+            screen_size: Vector2::new(screen_w as f64, screen_h as f64),
+        }
+    }
+
+    #[inline]
+    pub fn reset(&mut self) {
+        *self = Camera::new(self.screen_size.x as f32, self.screen_size.y as f32);
+    }
+
+    #[inline]
+    pub fn screen_to_world(&self, p: &Point2<f32>) -> Point2<f64> {
+        let s = Vector2::new(p.x as f64, p.y as f64) - self.screen_size / 2.0;
+        self.pos + s / self.zoom
+    }
+
+    // Pans by an amount of screen pixels. Stops following anything.
+    #[inline]
+    pub fn pan(&mut self, screen_dx: f64, screen_dy: f64) {
+        self.follow = Follow::Free;
+        self.pos += Vector2::new(screen_dx, screen_dy) / self.zoom;
+    }
+
+    // World distance that is `PICK_RADIUS` pixels on screen.
+    #[inline]
+    pub fn pick_radius(&self) -> f64 {
+        PICK_RADIUS / self.zoom
+    }
+
+    // Keep following whatever absorbed the followed body.
+    pub fn follow_merges(&mut self, merges: &[Merge]) {
+        if let Follow::Body(id) = self.follow {
+            self.follow = Follow::Body(world::survivor_of(id, merges));
+        }
+    }
+
+    // Centres on whatever is being followed. alpha is the interpolation between physics steps, so the
+    // camera moves with what is drawn.
+    pub fn update_follow(&mut self, world: &World, alpha: f64) {
+        let target = match self.follow {
+            Follow::Free => return,
+            Follow::Body(id) => world.bodies().get(&id).map(|b| b.borrow().interpolated_pos(alpha)),
+            Follow::CentreOfMass => world.centre_of_mass(alpha),
+        };
+
+        match target {
+            Some(pos) => self.pos = pos,
+            None => self.follow = Follow::Free,  // Body is gone, or there is nothing to follow
+        }
+    }
+
+    // Zooms while keeping the world point under `screen_point` in the same place on screen.
+    pub fn zoom_at(&mut self, screen_point: &Point2<f32>, factor: f64) {
+        let before = self.screen_to_world(screen_point);
+        self.zoom = (self.zoom * factor).clamp(ZOOM_LIMITS.0, ZOOM_LIMITS.1);
+        let after = self.screen_to_world(screen_point);
+        self.pos += before - after;
+    }
+
+    // The area of the world on screen. Given to `graphics::set_screen_coordinates` so everything
+    // can be drawn in world coordinates.
+    pub fn world_rect(&self) -> Rect {
+        let size = self.screen_size / self.zoom;
+        Rect::new(
+            (self.pos.x - size.x / 2.0) as f32,
+            (self.pos.y - size.y / 2.0) as f32,
+            size.x as f32,
+            size.y as f32,
+        )
+    }
+
+    // For going back to drawing in screen coordinates (HUD etc).
+    #[inline]
+    pub fn screen_rect(&self) -> Rect {
+        Rect::new(0.0, 0.0, self.screen_size.x as f32, self.screen_size.y as f32)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct CameraSaveData {
+    pub pos_x: f64,
+    pub pos_y: f64,
+    pub zoom: f64,
+    pub follow: Follow,
+}
+
+impl Camera {
+    pub fn save_data(&self) -> CameraSaveData {
+        CameraSaveData {
+            pos_x: self.pos.x,
+            pos_y: self.pos.y,
+            zoom: self.zoom,
+            follow: self.follow,
+        }
+    }
+
+    pub fn restore(&mut self, save: &CameraSaveData) {
+        self.pos = Point2::new(save.pos_x, save.pos_y);
+        self.zoom = save.zoom.clamp(ZOOM_LIMITS.0, ZOOM_LIMITS.1);
+        self.follow = save.follow;
+    }
+}
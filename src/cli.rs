@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+
+use crate::{clock, recorder, tools};
+
+pub const USAGE: &str = "Usage: Orbits [options]
+
+Options:
+    --scenario <file>           Start from a .json or .ron scenario file
+    --record-csv <file>         Record trajectories to a CSV file from the start
+    --record-npz <dir>          Write a NumPy .npz snapshot into <dir> every interval from the start
+    --record-diagnostics <file> Record energy, momentum and angular momentum to a CSV file from the start
+    --record-interval <secs>    Sim time between CSV samples or .npz snapshots (default 0.1)
+    --headless                  Run without a window, needs --scenario and --duration
+    --duration <secs>           Sim time to run for when headless
+    --physics-hz <hz>           Physics steps per second of sim time (default 120)
+    --max-substeps <n>          Most physics steps one frame can take at normal speed (default 8)
+    --replay <file>             Play back a replay recorded with F5
+    --tolerance <n>             Use an adaptive timestep with this tolerance (e.g 0.01)
+    --theta <n>                 Use Barnes-Hut gravity with this opening angle (e.g 0.5)
+    --seed <n>                  Seed for everything random, so runs can be repeated (default: random)
+    --help                      Show this message";
+
+#[derive(Debug, Clone)]
+pub struct CliOptions {
+    pub scenario: Option<PathBuf>,
+    pub record_csv: Option<PathBuf>,
+    pub record_npz: Option<PathBuf>,
+    pub record_diagnostics: Option<PathBuf>,
+    pub record_interval: f64,
+    pub headless: bool,
+    pub duration: Option<f64>,
+    pub physics_hz: f64,
+    pub max_substeps: u32,
+    pub replay: Option<PathBuf>,
+    pub seed: Option<u64>,
+    pub tolerance: Option<f64>,
+    pub theta: Option<f64>,
+    pub help: bool,
+}
+
+impl Default for CliOptions {
+    fn default() -> CliOptions {
+        CliOptions {
+            scenario: None,
+            record_csv: None,
+            record_npz: None,
+            record_diagnostics: None,
+            record_interval: recorder::DEFAULT_INTERVAL,
+            headless: false,
+            duration: None,
+            physics_hz: clock::DEFAULT_PHYSICS_HZ,
+            max_substeps: clock::DEFAULT_MAX_SUBSTEPS,
+            replay: None,
+            seed: None,
+            tolerance: None,
+            theta: None,
+            help: false,
+        }
+    }
+}
+
+impl CliOptions {
+    // Takes the arguments without the program name.
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<CliOptions, String> {
+        let mut opts = CliOptions::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--scenario" => opts.scenario = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--record-csv" => opts.record_csv = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--record-npz" => opts.record_npz = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--record-diagnostics" => opts.record_diagnostics = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--record-interval" => opts.record_interval = positive(&arg, args.next())?,
+                "--headless" => opts.headless = true,
+                "--duration" => opts.duration = Some(positive(&arg, args.next())?),
+                "--physics-hz" => opts.physics_hz = positive(&arg, args.next())?,
+                "--max-substeps" => {
+                    let v = value(&arg, args.next())?;
+                    opts.max_substeps = v
+                        .parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| format!("--max-substeps needs a whole number above 0, not \"{}\".", v))?;
+                },
+                "--replay" => opts.replay = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--tolerance" => opts.tolerance = Some(positive(&arg, args.next())?),
+                "--theta" => opts.theta = Some(positive(&arg, args.next())?),
+                "--seed" => {
+                    let v = value(&arg, args.next())?;
+                    opts.seed = Some(v.parse().map_err(|_| format!("--seed needs a whole number, not \"{}\".", v))?);
+                },
+                "--help" | "-h" => opts.help = true,
+                _ => return Err(format!("Unknown option \"{}\".", arg)),
+            }
+        }
+
+        if opts.headless && (opts.scenario.is_none() || opts.duration.is_none()) {
+            return Err("--headless needs both --scenario and --duration.".to_owned());
+        }
+
+        Ok(opts)
+    }
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value.", option))
+}
+
+fn positive(option: &str, v: Option<String>) -> Result<f64, String> {
+    value(option, v)?
+        .parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .ok_or_else(|| format!("{} needs a positive number.", option))
+}
+
+// For when no seed is given.
+pub fn random_seed() -> u64 {
+    tools::since_epoch().as_nanos() as u64
+}
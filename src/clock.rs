@@ -0,0 +1,159 @@
+use serde::{Serialize, Deserialize};
+
+pub const DEFAULT_PHYSICS_HZ: f64 = 120.0;
+pub const PHYSICS_HZ_PRESETS: [f64; 4] = [60.0, 120.0, 240.0, 480.0];
+// Caps how many steps one frame can trigger, so a long hitch doesn't cause a spiral of catching up.
+// Scaled up by the time scale, since speeding up is done with more steps rather than bigger ones.
+pub const DEFAULT_MAX_SUBSTEPS: u32 = 8;
+pub const TIME_SCALE_PRESETS: [f64; 11] = [0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 35.0, 50.0, 100.0];
+const DEFAULT_TIME_SCALE_INDEX: usize = 3;
+
+// Fixed timestep accumulator. Frame time goes in, whole physics steps of `step_dt` come out, and
+// whatever is left over is carried to the next frame.
+pub struct SimClock {
+    step_dt: f64,
+    pub max_substeps: u32,
+    accumulator: f64,
+    pub paused: bool,
+    time_scale_index: usize,
+    queued_steps: u32,  // Single steps requested while paused
+}
+
+impl SimClock {
+    pub fn new(physics_hz: f64, max_substeps: u32) -> SimClock {
+        SimClock {
+            step_dt: 1.0 / physics_hz,
+            max_substeps,
+            accumulator: 0.0,
+            paused: false,
+            time_scale_index: DEFAULT_TIME_SCALE_INDEX,
+            queued_steps: 0,
+        }
+    }
+
+    #[inline]
+    pub fn time_scale(&self) -> f64 {
+        TIME_SCALE_PRESETS[self.time_scale_index]
+    }
+
+    #[inline]
+    pub fn speed_up(&mut self) {
+        self.time_scale_index = (self.time_scale_index + 1).min(TIME_SCALE_PRESETS.len() - 1);
+    }
+
+    #[inline]
+    pub fn slow_down(&mut self) {
+        self.time_scale_index = self.time_scale_index.saturating_sub(1);
+    }
+
+    #[inline]
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    // Does one step on the next frame. Only does anything while paused.
+    #[inline]
+    pub fn single_step(&mut self) {
+        if self.paused {
+            self.queued_steps += 1;
+        }
+    }
+
+    #[inline]
+    pub fn step_dt(&self) -> f64 {
+        self.step_dt
+    }
+
+    #[inline]
+    pub fn physics_hz(&self) -> f64 {
+        1.0 / self.step_dt
+    }
+
+    #[inline]
+    pub fn set_physics_hz(&mut self, physics_hz: f64) {
+        self.step_dt = 1.0 / physics_hz;
+        self.accumulator = 0.0;
+    }
+
+    // Moves on to the next rate in `PHYSICS_HZ_PRESETS`.
+    pub fn cycle_physics_hz(&mut self) {
+        let current = self.physics_hz();
+        let next = PHYSICS_HZ_PRESETS
+            .iter()
+            .find(|&&hz| hz > current + 0.5)
+            .unwrap_or(&PHYSICS_HZ_PRESETS[0]);
+        self.set_physics_hz(*next);
+    }
+
+    // Returns the number of physics steps to take this frame. Time that would need more than
+    // `max_substeps` is dropped, so the simulation slows down rather than taking huge steps.
+    pub fn advance(&mut self, frame_dt: f64) -> u32 {
+        if self.paused {
+            let steps = self.queued_steps;
+            self.queued_steps = 0;
+            return steps;
+        }
+
+        self.accumulator += frame_dt * self.time_scale();
+        let max_steps = self.max_substeps * self.time_scale().ceil() as u32;
+
+        let mut steps = 0;
+        while self.accumulator >= self.step_dt && steps < max_steps {
+            self.accumulator -= self.step_dt;
+            steps += 1;
+        }
+
+        if steps == max_steps {
+            self.accumulator %= self.step_dt;
+        }
+
+        steps
+    }
+
+    // How far we are between the last physics step and the next one, from 0 to 1. Used to
+    // interpolate positions when drawing.
+    #[inline]
+    pub fn alpha(&self) -> f64 {
+        if self.paused {
+            1.0     // Show exactly where things are, especially after single stepping
+        } else {
+            (self.accumulator / self.step_dt).min(1.0)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct ClockSaveData {
+    pub step_dt: f64,
+    pub max_substeps: u32,
+    pub accumulator: f64,
+    pub paused: bool,
+    pub time_scale_index: usize,
+}
+
+impl SimClock {
+    pub fn save_data(&self) -> ClockSaveData {
+        ClockSaveData {
+            step_dt: self.step_dt,
+            max_substeps: self.max_substeps,
+            accumulator: self.accumulator,
+            paused: self.paused,
+            time_scale_index: self.time_scale_index,
+        }
+    }
+
+    pub fn restore(&mut self, save: &ClockSaveData) {
+        self.step_dt = save.step_dt;
+        self.max_substeps = save.max_substeps;
+        self.accumulator = save.accumulator;
+        self.paused = save.paused;
+        self.time_scale_index = save.time_scale_index.min(TIME_SCALE_PRESETS.len() - 1);
+        self.queued_steps = 0;
+    }
+}
+
+impl Default for SimClock {
+    fn default() -> SimClock {
+        SimClock::new(DEFAULT_PHYSICS_HZ, DEFAULT_MAX_SUBSTEPS)
+    }
+}
@@ -0,0 +1,306 @@
+// Broad phase for collisions, so only bodies that are near each other get checked. It only looks at
+// positions and radii, so it costs the same whichever gravity solver is used. Also swept (continuous)
+// checks for bodies that move further than their size in a step.
+
+use ggez::nalgebra as na;
+use na::{Point2, Vector2};
+
+use std::collections::HashMap;
+
+use crate::tools;
+
+// Most cells a body's width can span. Cells are sized for the typical body, so this stops one huge
+// planet from being put in thousands of them.
+const MAX_CELLS_ACROSS: f64 = 8.0;
+
+#[inline]
+pub fn is_colliding(p1: &Point2<f64>, p2: &Point2<f64>, r1: f64, r2: f64) -> bool {
+    aabb(p1, p2, r1, r2) && tools::distance_squared_to(p1, p2) <= (r1 + r2).powi(2)
+}
+
+// Whether the bounding boxes of two circles overlap.
+#[inline]
+pub fn aabb(p1: &Point2<f64>, p2: &Point2<f64>, r1: f64, r2: f64) -> bool {
+    let total_rad = r1 + r2;
+    (p2.x - p1.x).abs() <= total_rad && (p2.y - p1.y).abs() <= total_rad
+}
+
+/* Every pair (i, j), i < j, whose bounding boxes overlap, in order. Colliding pairs are always among
+   them, so only these need the exact check.
+
+   Bodies go in a uniform grid, in every cell their bounding box touches. The cells are the mean
+   diameter across (or an eighth of the biggest, if that's bigger), so most bodies are in 1-4 cells and
+   each only gets compared with its neighbours. Two bodies can share several cells, so a pair is only
+   looked at in the first cell of where their boxes overlap. This is near-linear unless everything is
+   piled on top of each other.
+*/
+pub fn overlapping_pairs(positions: &[Point2<f64>], radii: &[f64]) -> Vec<(usize, usize)> {
+    grid_pairs(positions, radii, cell_size(radii))
+}
+
+// Cell size for a set of radii, as described above.
+fn cell_size(radii: &[f64]) -> f64 {
+    let mean_radius = radii.iter().sum::<f64>() / radii.len() as f64;
+    let max_radius = radii.iter().cloned().fold(0.0, f64::max);
+    (2.0 * mean_radius).max(2.0 * max_radius / MAX_CELLS_ACROSS)
+}
+
+// `overlapping_pairs` with a given cell size. Anything wider than MAX_CELLS_ACROSS cells is left out of
+// the grid and checked against every other body instead, so a few of them don't fill thousands of
+// cells.
+fn grid_pairs(positions: &[Point2<f64>], radii: &[f64], cell_size: f64) -> Vec<(usize, usize)> {
+    if positions.len() < 2 {
+        return Vec::new();
+    }
+    if !(cell_size > 0.0 && cell_size.is_finite()) {
+        return brute_force_pairs(positions, radii);
+    }
+
+    // Range of cells covered by each body, inclusive
+    let cell = |x: f64| (x / cell_size).floor() as i64;
+    let bounds: Vec<((i64, i64), (i64, i64))> = positions
+        .iter()
+        .zip(radii.iter())
+        .map(|(p, r)| ((cell(p.x - r), cell(p.y - r)), (cell(p.x + r), cell(p.y + r))))
+        .collect();
+    let oversized: Vec<bool> = bounds
+        .iter()
+        .map(|&((x0, y0), (x1, y1))| (x1 - x0).max(y1 - y0) as f64 > MAX_CELLS_ACROSS)
+        .collect();
+
+    let mut grid: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for (i, &((x0, y0), (x1, y1))) in bounds.iter().enumerate().filter(|&(i, _)| !oversized[i]) {
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                grid.entry((x, y)).or_default().push(i);
+            }
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for (&(x, y), members) in grid.iter() {
+        for (n, &i) in members.iter().enumerate() {
+            for &j in members[n + 1..].iter() {
+                let (min_i, min_j) = (bounds[i].0, bounds[j].0);
+                let first_shared = (min_i.0.max(min_j.0), min_i.1.max(min_j.1));
+                if first_shared == (x, y) && aabb(&positions[i], &positions[j], radii[i], radii[j]) {
+                    pairs.push((i.min(j), i.max(j)));
+                }
+            }
+        }
+    }
+
+    for i in (0..positions.len()).filter(|&i| oversized[i]) {
+        for j in (0..positions.len()).filter(|&j| j != i && (!oversized[j] || j > i)) {
+            if aabb(&positions[i], &positions[j], radii[i], radii[j]) {
+                pairs.push((i.min(j), i.max(j)));
+            }
+        }
+    }
+
+    pairs.sort_unstable();
+    pairs
+}
+
+/* When, as a fraction of the step, two circles moving in straight lines from `start` to `end` first
+   touch. None if they don't during the step, 0 if they already are at the start.
+
+   With a the gap between them at the start and b how much it changes over the step, they touch when
+   |a + sb| = r1 + r2, i.e (b.b)s^2 + 2(a.b)s + a.a - (r1 + r2)^2 = 0, and the smaller root is when
+   they meet.
+*/
+pub fn time_of_impact(
+    start1: &Point2<f64>, end1: &Point2<f64>, r1: f64,
+    start2: &Point2<f64>, end2: &Point2<f64>, r2: f64,
+) -> Option<f64> {
+    let a: Vector2<f64> = start2 - start1;
+    let b: Vector2<f64> = (end2 - end1) - a;
+    let c = a.norm_squared() - (r1 + r2).powi(2);
+    if c <= 0.0 {
+        return Some(0.0);
+    }
+
+    let bb = b.norm_squared();
+    let ab = a.dot(&b);
+    let discriminant = ab * ab - bb * c;
+    if bb == 0.0 || ab >= 0.0 || discriminant < 0.0 {
+        return None;    // Not moving relative to each other, moving apart, or missing
+    }
+
+    let s = (-ab - discriminant.sqrt()) / bb;
+    if s <= 1.0 {
+        Some(s)
+    } else {
+        None
+    }
+}
+
+// Pairs whose paths over a step might cross, in the same form as `overlapping_pairs`. Each body is
+// covered by a circle around the middle of its path, big enough for the body at both ends. The grid is
+// still sized from the bodies themselves, so one fast projectile doesn't make the cells huge for
+// everything else.
+pub fn swept_pairs(start: &[Point2<f64>], end: &[Point2<f64>], radii: &[f64]) -> Vec<(usize, usize)> {
+    let (centres, swept_radii): (Vec<_>, Vec<_>) = start
+        .iter()
+        .zip(end.iter())
+        .zip(radii.iter())
+        .map(|((s, e), r)| (na::center(s, e), r + na::distance(s, e) / 2.0))
+        .unzip();
+    grid_pairs(&centres, &swept_radii, cell_size(radii))
+}
+
+// Checks every pair. Used when the grid can't be sized, e.g all the radii are zero.
+fn brute_force_pairs(positions: &[Point2<f64>], radii: &[f64]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for i in 0..positions.len() {
+        for j in i + 1..positions.len() {
+            if aabb(&positions[i], &positions[j], radii[i], radii[j]) {
+                pairs.push((i, j));
+            }
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn colliding(pairs: &[(usize, usize)], positions: &[Point2<f64>], radii: &[f64]) -> Vec<(usize, usize)> {
+        pairs
+            .iter()
+            .cloned()
+            .filter(|&(i, j)| is_colliding(&positions[i], &positions[j], radii[i], radii[j]))
+            .collect()
+    }
+
+    fn check(positions: &[Point2<f64>], radii: &[f64]) {
+        let expected = colliding(&brute_force_pairs(positions, radii), positions, radii);
+        let pairs = overlapping_pairs(positions, radii);
+        assert!(pairs.windows(2).all(|w| w[0] < w[1]), "pairs not sorted or repeated");
+        assert!(pairs.iter().all(|&(i, j)| i < j));
+        assert_eq!(colliding(&pairs, positions, radii), expected);
+    }
+
+    fn random_scene(rng: &mut StdRng, n: usize, spread: f64, max_radius: f64) -> (Vec<Point2<f64>>, Vec<f64>) {
+        let positions = (0..n)
+            .map(|_| Point2::new(rng.gen_range(-spread, spread), rng.gen_range(-spread, spread)))
+            .collect();
+        let radii = (0..n).map(|_| rng.gen_range(0.1, max_radius)).collect();
+        (positions, radii)
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for &(n, spread, max_radius) in &[(2, 10.0, 5.0), (50, 100.0, 5.0), (500, 1000.0, 10.0), (300, 50.0, 20.0)] {
+            for _ in 0..20 {
+                let (positions, radii) = random_scene(&mut rng, n, spread, max_radius);
+                check(&positions, &radii);
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_sizes() {
+        // A few huge planets among lots of dust, so big bodies span many cells
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..20 {
+            let (mut positions, mut radii) = random_scene(&mut rng, 400, 2000.0, 2.0);
+            for _ in 0..3 {
+                positions.push(Point2::new(rng.gen_range(-2000.0, 2000.0), rng.gen_range(-2000.0, 2000.0)));
+                radii.push(rng.gen_range(100.0, 600.0));
+            }
+            check(&positions, &radii);
+        }
+    }
+
+    #[test]
+    fn swept_matches_brute_force() {
+        // Every pair that touches at some point along its paths should be a swept pair
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..20 {
+            let (start, radii) = random_scene(&mut rng, 300, 500.0, 3.0);
+            let end: Vec<_> = start
+                .iter()
+                .map(|p| p + Vector2::new(rng.gen_range(-60.0, 60.0), rng.gen_range(-60.0, 60.0)))
+                .collect();
+
+            let pairs = swept_pairs(&start, &end, &radii);
+            for i in 0..start.len() {
+                for j in i + 1..start.len() {
+                    if time_of_impact(&start[i], &end[i], radii[i], &start[j], &end[j], radii[j]).is_some() {
+                        assert!(pairs.binary_search(&(i, j)).is_ok(), "missed swept pair {:?}", (i, j));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn swept_fast_bodies() {
+        // A few bodies crossing most of the scene in one step, so they're far wider than the cells
+        let mut rng = StdRng::seed_from_u64(4);
+        for _ in 0..20 {
+            let (start, radii) = random_scene(&mut rng, 300, 500.0, 3.0);
+            let mut end = start.clone();
+            for p in end.iter_mut().take(5) {
+                *p += Vector2::new(rng.gen_range(-1000.0, 1000.0), rng.gen_range(-1000.0, 1000.0));
+            }
+
+            let pairs = swept_pairs(&start, &end, &radii);
+            assert!(pairs.windows(2).all(|w| w[0] < w[1]), "pairs not sorted or repeated");
+            for i in 0..start.len() {
+                for j in i + 1..start.len() {
+                    if time_of_impact(&start[i], &end[i], radii[i], &start[j], &end[j], radii[j]).is_some() {
+                        assert!(pairs.binary_search(&(i, j)).is_ok(), "missed swept pair {:?}", (i, j));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn time_of_impact_cases() {
+        let origin = Point2::new(0.0, 0.0);
+
+        // Fired straight through a stationary body: touches a quarter of the way along
+        let toi = time_of_impact(&Point2::new(-20.0, 0.0), &Point2::new(20.0, 0.0), 1.0, &origin, &origin, 9.0);
+        assert!((toi.unwrap() - 0.25).abs() < 1e-12);
+
+        // The same, from the other body's point of view
+        let toi = time_of_impact(&origin, &origin, 9.0, &Point2::new(-20.0, 0.0), &Point2::new(20.0, 0.0), 1.0);
+        assert!((toi.unwrap() - 0.25).abs() < 1e-12);
+
+        // Passing by, stopping short, moving apart, overlapping already
+        assert!(time_of_impact(&Point2::new(-20.0, 20.0), &Point2::new(20.0, 20.0), 1.0, &origin, &origin, 9.0).is_none());
+        assert!(time_of_impact(&Point2::new(-20.0, 0.0), &Point2::new(-15.0, 0.0), 1.0, &origin, &origin, 9.0).is_none());
+        assert!(time_of_impact(&Point2::new(-20.0, 0.0), &Point2::new(-40.0, 0.0), 1.0, &origin, &origin, 9.0).is_none());
+        assert_eq!(time_of_impact(&Point2::new(5.0, 0.0), &Point2::new(50.0, 0.0), 1.0, &origin, &origin, 9.0), Some(0.0));
+
+        // Both moving, meeting in the middle
+        let toi = time_of_impact(
+            &Point2::new(-10.0, 0.0), &Point2::new(10.0, 0.0), 1.0,
+            &Point2::new(10.0, 0.0), &Point2::new(-10.0, 0.0), 1.0,
+        );
+        assert!((toi.unwrap() - 0.45).abs() < 1e-12);
+    }
+
+    #[test]
+    fn edge_cases() {
+        // Nothing, one body
+        assert!(overlapping_pairs(&[], &[]).is_empty());
+        assert!(overlapping_pairs(&[Point2::new(0.0, 0.0)], &[1.0]).is_empty());
+
+        // Just touching counts, in every direction (the old check only caught one side)
+        let touching = [Point2::new(0.0, 0.0), Point2::new(2.0, 0.0), Point2::new(-2.0, 0.0), Point2::new(0.0, -2.0)];
+        check(&touching, &[1.0; 4]);
+        assert_eq!(colliding(&overlapping_pairs(&touching, &[1.0; 4]), &touching, &[1.0; 4]), vec![(0, 1), (0, 2), (0, 3)]);
+
+        // On top of each other, across cell boundaries, and with zero radii
+        check(&[Point2::new(5.0, 5.0); 10], &[1.0; 10]);
+        check(&[Point2::new(-0.5, -0.5), Point2::new(0.5, 0.5), Point2::new(1.5, -0.5)], &[0.8, 0.8, 0.8]);
+        check(&[Point2::new(0.0, 0.0), Point2::new(0.0, 0.0), Point2::new(1.0, 0.0)], &[0.0, 0.0, 0.0]);
+    }
+}
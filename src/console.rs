@@ -0,0 +1,312 @@
+use ggez::graphics::{self, DrawParam, Rect, Text};
+use ggez::nalgebra as na;
+use ggez::{Context, GameResult};
+use na::Point2;
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::body::BodyID;
+
+const MESSAGE_LIFETIME: Duration = Duration::from_secs(5);
+const LINE_HEIGHT: f32 = 20.0;
+
+pub const HELP: &str = "Commands: rename <body> <name>, find <name>, delete <body>, colour <body> <#rrggbb>, \
+import <file>, export <file>, npz <file>, save <name>, load <name>, saves, timeline [budget MB], replay <file>, tolerance [value|off], theta [value|off], substeps [n], softening [body] [length|default], diagnostics <file.csv|stop>. Bodies can be given by name or id (quote names with spaces).";
+
+// A body given by the user, either by id (`12` or `#12`) or by name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BodyRef {
+    Id(BodyID),
+    Name(String),
+}
+
+impl BodyRef {
+    fn parse(s: &str) -> BodyRef {
+        match s.trim_start_matches('#').parse::<BodyID>() {
+            Ok(id) => BodyRef::Id(id),
+            Err(_) => BodyRef::Name(s.to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Rename { body: BodyRef, name: String },
+    Find(String),
+    Delete(BodyRef),
+    Colour { body: BodyRef, colour: [f32; 4] },
+    Import(PathBuf),    // Scenario file (.json or .ron)
+    Export(PathBuf),
+    Npz(PathBuf),       // NumPy snapshot of the bodies
+    Diagnostics(Option<PathBuf>),   // Start recording diagnostics to a CSV file, or stop with None
+    Save(String),       // Named save slot
+    Load(String),
+    Saves,              // Open the save browser
+    Timeline(Option<usize>),    // Show timeline usage, or set its memory budget in MB
+    Replay(PathBuf),    // Play back a recorded replay
+    Tolerance(Option<Option<f64>>),     // Show the timestep, or set the adaptive tolerance (None for a fixed step)
+    Theta(Option<Option<f64>>),     // Show the gravity solver, or use Barnes-Hut with this opening angle (None for direct sum)
+    Substeps(Option<u32>),      // Show or set the most physics steps a frame can take at normal speed
+    Softening(Option<f64>),     // Show or set the global softening length
+    BodySoftening { body: BodyRef, length: Option<f64> },  // None goes back to the global one
+    Help,
+}
+
+impl Command {
+    // Anything that reads or writes files, which replays aren't allowed to do.
+    pub fn uses_files(&self) -> bool {
+        matches!(
+            *self,
+            Command::Import(_) | Command::Export(_) | Command::Npz(_) | Command::Diagnostics(_)
+                | Command::Save(_) | Command::Load(_) | Command::Replay(_)
+        )
+    }
+
+    pub fn parse(input: &str) -> Result<Command, String> {
+        let args = tokenize(input);
+        let (name, args) = match args.split_first() {
+            Some((name, args)) => (name.to_lowercase(), args),
+            None => return Err("No command given.".to_owned()),
+        };
+
+        match name.as_str() {
+            "rename" => {
+                if args.len() < 2 {
+                    return Err("Usage: rename <body> <new name>".to_owned());
+                }
+                Ok(Command::Rename { body: BodyRef::parse(&args[0]), name: args[1..].join(" ") })
+            },
+            "find" => {
+                if args.is_empty() {
+                    return Err("Usage: find <name>".to_owned());
+                }
+                Ok(Command::Find(args.join(" ")))
+            },
+            "delete" | "del" | "rm" => {
+                if args.is_empty() {
+                    return Err("Usage: delete <body>".to_owned());
+                }
+                Ok(Command::Delete(BodyRef::parse(&args.join(" "))))
+            },
+            "colour" | "color" => {
+                if args.len() != 2 {
+                    return Err("Usage: colour <body> <#rrggbb>".to_owned());
+                }
+                Ok(Command::Colour { body: BodyRef::parse(&args[0]), colour: parse_colour(&args[1])? })
+            },
+            "import" | "export" => {
+                if args.is_empty() {
+                    return Err(format!("Usage: {} <file.json|file.ron>", name));
+                }
+                let path = PathBuf::from(args.join(" "));
+                Ok(if name == "import" { Command::Import(path) } else { Command::Export(path) })
+            },
+            "npz" => {
+                if args.is_empty() {
+                    return Err("Usage: npz <file.npz>".to_owned());
+                }
+                Ok(Command::Npz(PathBuf::from(args.join(" "))))
+            },
+            "diagnostics" | "diag" => match args.first() {
+                None => Err("Usage: diagnostics <file.csv> or diagnostics stop".to_owned()),
+                Some(stop) if args.len() == 1 && (stop.eq_ignore_ascii_case("stop") || stop.eq_ignore_ascii_case("off")) => {
+                    Ok(Command::Diagnostics(None))
+                },
+                Some(_) => Ok(Command::Diagnostics(Some(PathBuf::from(args.join(" "))))),
+            },
+            "save" | "load" => {
+                if args.is_empty() {
+                    return Err(format!("Usage: {} <name>", name));
+                }
+                let slot = args.join(" ");
+                Ok(if name == "save" { Command::Save(slot) } else { Command::Load(slot) })
+            },
+            "saves" => Ok(Command::Saves),
+            "timeline" => match args.first() {
+                None => Ok(Command::Timeline(None)),
+                Some(mb) => mb
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&mb| mb > 0)
+                    .map(|mb| Command::Timeline(Some(mb)))
+                    .ok_or_else(|| "Usage: timeline [memory budget in MB]".to_owned()),
+            },
+            "replay" => {
+                if args.is_empty() {
+                    return Err("Usage: replay <file.orbrep>".to_owned());
+                }
+                Ok(Command::Replay(PathBuf::from(args.join(" "))))
+            },
+            "tolerance" | "tol" => match args.first().map(|a| a.to_lowercase()) {
+                None => Ok(Command::Tolerance(None)),
+                Some(ref off) if off == "off" || off == "fixed" => Ok(Command::Tolerance(Some(None))),
+                Some(value) => value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|t| t.is_finite() && *t > 0.0)
+                    .map(|t| Command::Tolerance(Some(Some(t))))
+                    .ok_or_else(|| "Usage: tolerance [positive number|off]".to_owned()),
+            },
+            "theta" => match args.first().map(|a| a.to_lowercase()) {
+                None => Ok(Command::Theta(None)),
+                Some(ref off) if off == "off" || off == "direct" => Ok(Command::Theta(Some(None))),
+                Some(value) => value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|t| t.is_finite() && *t > 0.0)
+                    .map(|t| Command::Theta(Some(Some(t))))
+                    .ok_or_else(|| "Usage: theta [positive number|off]".to_owned()),
+            },
+            "substeps" => match args.first() {
+                None => Ok(Command::Substeps(None)),
+                Some(n) => n
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .map(|n| Command::Substeps(Some(n)))
+                    .ok_or_else(|| "Usage: substeps [whole number above 0]".to_owned()),
+            },
+            "softening" | "soften" => match args.len() {
+                0 => Ok(Command::Softening(None)),
+                1 => Ok(Command::Softening(Some(parse_length(&args[0])?))),
+                2 => {
+                    let length = if args[1].eq_ignore_ascii_case("default") { None } else { Some(parse_length(&args[1])?) };
+                    Ok(Command::BodySoftening { body: BodyRef::parse(&args[0]), length })
+                },
+                _ => Err("Usage: softening [length] or softening <body> <length|default>".to_owned()),
+            },
+            "help" | "?" => Ok(Command::Help),
+            _ => Err(format!("Unknown command \"{}\". Type help for a list.", name)),
+        }
+    }
+}
+
+fn parse_length(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|l| l.is_finite() && *l >= 0.0)
+        .ok_or_else(|| format!("\"{}\" isn't a length, expected a number 0 or over.", s))
+}
+
+// #rrggbb, the # is optional.
+fn parse_colour(s: &str) -> Result<[f32; 4], String> {
+    let hex = s.trim_start_matches('#');
+    let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6);
+
+    match value {
+        Some(v) => Ok([
+            ((v >> 16) & 0xff) as f32 / 255.0,
+            ((v >> 8) & 0xff) as f32 / 255.0,
+            (v & 0xff) as f32 / 255.0,
+            1.0,
+        ]),
+        None => Err(format!("\"{}\" isn't a colour, expected something like #ff8800.", s)),
+    }
+}
+
+// Splits on whitespace, but keeps anything in double quotes together.
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for ch in input.chars() {
+        match ch {
+            '"' => {
+                if in_quotes {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                in_quotes = !in_quotes;
+            },
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            },
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+// Single line text input at the bottom of the screen, plus the last message it printed.
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    message: Option<(String, Instant)>,
+}
+
+impl Console {
+    #[inline]
+    pub fn open(&mut self) {
+        self.open = true;
+        self.input.clear();
+    }
+
+    // Open with something already typed, e.g "save ".
+    pub fn open_with(&mut self, input: &str) {
+        self.open = true;
+        self.input = input.to_owned();
+    }
+
+    #[inline]
+    pub fn close(&mut self) {
+        self.open = false;
+        self.input.clear();
+    }
+
+    #[inline]
+    pub fn push_char(&mut self, ch: char) {
+        if !ch.is_control() {
+            self.input.push(ch);
+        }
+    }
+
+    #[inline]
+    pub fn backspace(&mut self) {
+        self.input.pop();
+    }
+
+    // Closes the console and parses whatever was typed. None if nothing was.
+    pub fn submit(&mut self) -> Option<Result<Command, String>> {
+        let input = std::mem::take(&mut self.input);
+        self.close();
+
+        if input.trim().is_empty() {
+            None
+        } else {
+            Some(Command::parse(&input))
+        }
+    }
+
+    pub fn show_message<S: Into<String>>(&mut self, msg: S) {
+        let msg = msg.into();
+        println!("{}", msg);
+        self.message = Some((msg, Instant::now()));
+    }
+
+    pub fn draw(&self, ctx: &mut Context, screen: Rect) -> GameResult {
+        let mut y = screen.h - 10.0 - LINE_HEIGHT;
+
+        if self.open {
+            let prompt = Text::new(format!("> {}_", self.input));
+            graphics::draw(ctx, &prompt, DrawParam::default().dest(Point2::new(10.0, y)))?;
+            y -= LINE_HEIGHT;
+        }
+
+        if let Some((ref msg, time)) = self.message {
+            if time.elapsed() < MESSAGE_LIFETIME || self.open {
+                let text = Text::new(msg.as_str());
+                graphics::draw(ctx, &text, DrawParam::default().dest(Point2::new(10.0, y)))?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+// Conserved quantities, for telling whether a run is still physically sane. Energy is only conserved
+// once what merges took out (`World::collision_energy`) is added back on.
+
+use ggez::nalgebra as na;
+use na::{Point2, Vector2};
+
+use crate::{body::Mobile, world::World};
+
+// How often the HUD measures, in seconds of real time. Measuring costs about as much as a step, since
+// the potential energy is worked out by the gravity solver, so not every frame.
+const MEASURE_PERIOD: f64 = 0.25;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Diagnostics {
+    pub time: f64,
+    pub kinetic: f64,
+    pub potential: f64,     // Softened and (with Barnes-Hut) approximated the same way as the forces
+    pub collision_energy: f64,  // Taken out by merges, `World::collision_energy` at the time
+    pub momentum: Vector2<f64>,
+    pub angular_momentum: f64,  // About the barycentre, in the barycentre's frame
+    // Sums of the magnitudes of each body's momentum and angular momentum, so there is something to
+    // compare drift against even when the totals are (or should be) zero.
+    momentum_scale: f64,
+    angular_momentum_scale: f64,
+}
+
+// Change since some starting point, relative to it. None if there is nothing to compare against.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Drift {
+    pub energy: Option<f64>,
+    pub momentum: Option<f64>,
+    pub angular_momentum: Option<f64>,
+}
+
+impl Diagnostics {
+    pub fn measure(world: &World) -> Diagnostics {
+        let bodies: Vec<_> = world.bodies().values().map(|b| b.borrow()).collect();
+
+        let positions: Vec<_> = bodies.iter().map(|b| b.pos).collect();
+        let masses: Vec<_> = bodies.iter().map(|b| b.mass).collect();
+        let softening_sq: Vec<_> = bodies.iter().map(|b| world.softening_sq(b)).collect();
+        let potential = world.settings.gravity_solver.potential_energy(&positions, &masses, &softening_sq);
+
+        let mut kinetic = 0.0;
+        let mut mass = 0.0;
+        let mut momentum = Vector2::new(0.0, 0.0);
+        let mut weighted_pos = Vector2::new(0.0, 0.0);
+        let mut momentum_scale = 0.0;
+
+        for body in bodies.iter() {
+            kinetic += body.kinetic_energy();
+            mass += body.mass;
+            momentum += body.vel() * body.mass;
+            weighted_pos += body.pos.coords * body.mass;
+            momentum_scale += body.vel().norm() * body.mass;
+        }
+
+        let (barycentre, barycentre_vel) = if mass > 0.0 {
+            (Point2::from(weighted_pos / mass), momentum / mass)
+        } else {
+            (Point2::origin(), Vector2::new(0.0, 0.0))
+        };
+
+        let mut angular_momentum = 0.0;
+        let mut angular_momentum_scale = 0.0;
+        for body in bodies.iter() {
+            let l = body.mass * cross(&(body.pos - barycentre), &(body.vel() - barycentre_vel));
+            angular_momentum += l;
+            angular_momentum_scale += l.abs();
+        }
+
+        Diagnostics {
+            time: world.time(),
+            kinetic,
+            potential,
+            collision_energy: world.collision_energy(),
+            momentum,
+            angular_momentum,
+            momentum_scale,
+            angular_momentum_scale,
+        }
+    }
+
+    #[inline]
+    pub fn total_energy(&self) -> f64 {
+        self.kinetic + self.potential
+    }
+
+    // What merges took out since `start`, added back on.
+    #[inline]
+    pub fn energy_with_collisions(&self, start: &Diagnostics) -> f64 {
+        self.total_energy() + self.collision_energy - start.collision_energy
+    }
+
+    pub fn drift_since(&self, start: &Diagnostics) -> Drift {
+        Drift {
+            energy: relative(self.energy_with_collisions(start) - start.total_energy(), start.total_energy().abs()),
+            momentum: relative((self.momentum - start.momentum).norm(), start.momentum_scale),
+            angular_momentum: relative(self.angular_momentum - start.angular_momentum, start.angular_momentum_scale),
+        }
+    }
+}
+
+#[inline]
+fn relative(change: f64, scale: f64) -> Option<f64> {
+    if scale > 0.0 {
+        Some(change / scale)
+    } else {
+        None
+    }
+}
+
+#[inline]
+fn cross(a: &Vector2<f64>, b: &Vector2<f64>) -> f64 {
+    a.x * b.y - a.y * b.x
+}
+
+// Keeps the latest measurement for the HUD, and the one drift is measured from. The start is taken
+// again after anything that changes the scene on purpose, e.g placing or deleting a body or loading.
+#[derive(Default)]
+pub struct DiagnosticsTracker {
+    start: Option<Diagnostics>,
+    latest: Option<Diagnostics>,
+    timer: f64,
+}
+
+impl DiagnosticsTracker {
+    // Measures if it has been long enough since the last time, or if the start needs taking.
+    pub fn update(&mut self, world: &World, frame_dt: f64) {
+        self.timer -= frame_dt;
+        if self.timer > 0.0 && self.start.is_some() {
+            return;
+        }
+        self.timer = MEASURE_PERIOD;
+
+        let now = Diagnostics::measure(world);
+        self.start.get_or_insert(now);
+        self.latest = Some(now);
+    }
+
+    // Starts measuring drift from scratch on the next update.
+    pub fn reset(&mut self) {
+        self.start = None;
+        self.latest = None;
+    }
+
+    #[inline]
+    pub fn latest(&self) -> Option<&Diagnostics> {
+        self.latest.as_ref()
+    }
+
+    #[inline]
+    pub fn start(&self) -> Option<&Diagnostics> {
+        self.start.as_ref()
+    }
+
+    pub fn drift(&self) -> Option<Drift> {
+        Some(self.latest?.drift_since(self.start.as_ref()?))
+    }
+}
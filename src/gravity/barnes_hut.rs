@@ -0,0 +1,318 @@
+use ggez::nalgebra as na;
+use na::{Point2, Vector2};
+
+use super::pair_softening_sq;
+use crate::tools;
+
+// Past this depth bodies share a leaf instead of splitting further, otherwise bodies sitting on top
+// of each other would subdivide forever.
+const MAX_DEPTH: usize = 32;
+
+struct Node {
+    centre: Point2<f64>,
+    half_size: f64,
+    mass: f64,
+    com: Point2<f64>,           // Centre of mass
+    softening_sq: f64,          // Mass weighted mean of the bodies' softening (squared), for approximating the node
+    children: Option<usize>,    // Index of the first of 4 children (NW, NE, SW, SE)
+    bodies: Vec<usize>,         // Only leaves hold bodies
+}
+
+impl Node {
+    fn new(centre: Point2<f64>, half_size: f64) -> Node {
+        Node {
+            centre,
+            half_size,
+            mass: 0.0,
+            com: centre,
+            softening_sq: 0.0,
+            children: None,
+            bodies: Vec::new(),
+        }
+    }
+
+    #[inline]
+    fn quadrant(&self, pos: &Point2<f64>) -> usize {
+        let east = (pos.x >= self.centre.x) as usize;
+        let south = (pos.y >= self.centre.y) as usize;
+        south * 2 + east
+    }
+
+    #[inline]
+    fn contains(&self, pos: &Point2<f64>) -> bool {
+        (pos.x - self.centre.x).abs() <= self.half_size && (pos.y - self.centre.y).abs() <= self.half_size
+    }
+}
+
+pub struct QuadTree {
+    nodes: Vec<Node>,
+}
+
+impl QuadTree {
+    pub fn new(positions: &[Point2<f64>], masses: &[f64], softening_sq: &[f64]) -> QuadTree {
+        let mut min = Point2::new(f64::MAX, f64::MAX);
+        let mut max = Point2::new(f64::MIN, f64::MIN);
+        for p in positions.iter() {
+            min.x = min.x.min(p.x);
+            min.y = min.y.min(p.y);
+            max.x = max.x.max(p.x);
+            max.y = max.y.max(p.y);
+        }
+
+        let centre = if positions.is_empty() {
+            Point2::new(0.0, 0.0)
+        } else {
+            Point2::new((min.x + max.x) / 2.0, (min.y + max.y) / 2.0)
+        };
+        // Small margin so bodies on the edge are still inside the root.
+        let half_size = ((max.x - min.x).max(max.y - min.y) / 2.0).max(0.0) + 1.0;
+
+        let mut tree = QuadTree {
+            nodes: Vec::with_capacity(positions.len() * 2 + 1),
+        };
+        tree.nodes.push(Node::new(centre, half_size));
+
+        for i in 0..positions.len() {
+            tree.insert(i, positions);
+        }
+        tree.calculate_mass_distribution(positions, masses, softening_sq);
+
+        tree
+    }
+
+    fn insert(&mut self, body: usize, positions: &[Point2<f64>]) {
+        let pos = &positions[body];
+        let mut node = 0;
+        let mut depth = 0;
+
+        loop {
+            if let Some(first) = self.nodes[node].children {
+                node = first + self.nodes[node].quadrant(pos);
+                depth += 1;
+            } else if self.nodes[node].bodies.is_empty() || depth >= MAX_DEPTH {
+                self.nodes[node].bodies.push(body);
+                return;
+            } else {
+                // Occupied leaf, so split it and push the old occupants down a level.
+                let first = self.subdivide(node);
+                let occupants = std::mem::take(&mut self.nodes[node].bodies);
+                for b in occupants {
+                    let child = first + self.nodes[node].quadrant(&positions[b]);
+                    self.nodes[child].bodies.push(b);
+                }
+            }
+        }
+    }
+
+    fn subdivide(&mut self, node: usize) -> usize {
+        let first = self.nodes.len();
+        let (centre, quarter) = (self.nodes[node].centre, self.nodes[node].half_size / 2.0);
+
+        for &(dx, dy) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].iter() {
+            self.nodes.push(Node::new(
+                Point2::new(centre.x + dx * quarter, centre.y + dy * quarter),
+                quarter,
+            ));
+        }
+        self.nodes[node].children = Some(first);
+
+        first
+    }
+
+    fn calculate_mass_distribution(&mut self, positions: &[Point2<f64>], masses: &[f64], softening_sq: &[f64]) {
+        // Children are always pushed after their parent, so going backwards visits children first.
+        for n in (0..self.nodes.len()).rev() {
+            let mut mass = 0.0;
+            let mut weighted = Vector2::new(0.0, 0.0);
+            let mut weighted_softening = 0.0;
+
+            if let Some(first) = self.nodes[n].children {
+                for child in &self.nodes[first..first + 4] {
+                    mass += child.mass;
+                    weighted += child.com.coords * child.mass;
+                    weighted_softening += child.softening_sq * child.mass;
+                }
+            } else {
+                for &b in self.nodes[n].bodies.iter() {
+                    mass += masses[b];
+                    weighted += positions[b].coords * masses[b];
+                    weighted_softening += softening_sq[b] * masses[b];
+                }
+            }
+
+            self.nodes[n].mass = mass;
+            if mass > 0.0 {
+                self.nodes[n].com = Point2::from(weighted / mass);
+                self.nodes[n].softening_sq = weighted_softening / mass;
+            }
+        }
+    }
+
+    // Force on a body from everything else in the tree.
+    pub fn force_on(
+        &self,
+        body: usize,
+        positions: &[Point2<f64>],
+        masses: &[f64],
+        softening_sq: &[f64],
+        theta: f64,
+        stack: &mut Vec<usize>,
+    ) -> Vector2<f64> {
+        let (pos, mass) = (&positions[body], masses[body]);
+        let mut force = Vector2::new(0.0, 0.0);
+        self.walk(body, positions, masses, softening_sq, theta, stack, |other_mass, other_pos, pair_sq| {
+            force += tools::newtonian_grav(mass, other_mass, pos, other_pos, pair_sq);
+        });
+        force
+    }
+
+    // Potential energy of a body with everything else in the tree, approximated the same way.
+    pub fn potential_of(
+        &self,
+        body: usize,
+        positions: &[Point2<f64>],
+        masses: &[f64],
+        softening_sq: &[f64],
+        theta: f64,
+        stack: &mut Vec<usize>,
+    ) -> f64 {
+        let (pos, mass) = (&positions[body], masses[body]);
+        let mut potential = 0.0;
+        self.walk(body, positions, masses, softening_sq, theta, stack, |other_mass, other_pos, pair_sq| {
+            potential += tools::newtonian_potential(mass, other_mass, pos, other_pos, pair_sq);
+        });
+        potential
+    }
+
+    // Calls `interact` with the mass, position and pair softening (squared) of every other body, or of
+    // the node standing in for them if it's far enough away.
+    #[allow(clippy::too_many_arguments)]
+    fn walk<F: FnMut(f64, &Point2<f64>, f64)>(
+        &self,
+        body: usize,
+        positions: &[Point2<f64>],
+        masses: &[f64],
+        softening_sq: &[f64],
+        theta: f64,
+        stack: &mut Vec<usize>,
+        mut interact: F,
+    ) {
+        let (pos, eps_sq) = (&positions[body], softening_sq[body]);
+
+        stack.clear();
+        stack.push(0);
+
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if node.mass <= 0.0 {
+                continue;
+            }
+
+            match node.children {
+                None => {
+                    for &b in node.bodies.iter() {
+                        if b != body {
+                            interact(masses[b], &positions[b], pair_softening_sq(eps_sq, softening_sq[b]));
+                        }
+                    }
+                }
+                Some(first) => {
+                    // s/d < theta, using squares to avoid the sqrt. Never approximate a node the body is inside of.
+                    let size = node.half_size * 2.0;
+                    if !node.contains(pos) && size * size < theta * theta * tools::distance_squared_to(pos, &node.com) {
+                        interact(node.mass, &node.com, pair_softening_sq(eps_sq, node.softening_sq));
+                    } else {
+                        stack.extend(first..first + 4);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn forces(positions: &[Point2<f64>], masses: &[f64], softening_sq: &[f64], theta: f64) -> Vec<Vector2<f64>> {
+    let tree = QuadTree::new(positions, masses, softening_sq);
+    let mut stack = Vec::with_capacity(64);
+
+    (0..positions.len())
+        .map(|i| tree.force_on(i, positions, masses, softening_sq, theta, &mut stack))
+        .collect()
+}
+
+// Total potential energy. Every pair is counted from both ends, so it's halved.
+pub fn potential_energy(positions: &[Point2<f64>], masses: &[f64], softening_sq: &[f64], theta: f64) -> f64 {
+    let tree = QuadTree::new(positions, masses, softening_sq);
+    let mut stack = Vec::with_capacity(64);
+
+    (0..positions.len())
+        .map(|i| tree.potential_of(i, positions, masses, softening_sq, theta, &mut stack))
+        .sum::<f64>() / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gravity::{direct_potential_energy, direct_sum, DEFAULT_THETA};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    // A clump of bodies with a range of masses, some softened.
+    fn cluster(n: usize, seed: u64) -> (Vec<Point2<f64>>, Vec<f64>, Vec<f64>) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let positions = (0..n)
+            .map(|_| Point2::new(rng.gen_range(-500.0, 500.0), rng.gen_range(-500.0, 500.0)))
+            .collect();
+        let masses = (0..n).map(|_| rng.gen_range(1.0, 1000.0)).collect();
+        let softening_sq = (0..n).map(|i| if i % 3 == 0 { 4.0 } else { 0.0 }).collect();
+        (positions, masses, softening_sq)
+    }
+
+    // Error in each force over the typical size of a force.
+    fn errors(approx: &[Vector2<f64>], exact: &[Vector2<f64>]) -> Vec<f64> {
+        let rms = (exact.iter().map(|f| f.norm_squared()).sum::<f64>() / exact.len() as f64).sqrt();
+        approx.iter().zip(exact.iter()).map(|(a, e)| (a - e).norm() / rms).collect()
+    }
+
+    #[test]
+    fn theta_zero_matches_direct_sum() {
+        let (positions, masses, softening_sq) = cluster(300, 1);
+        let exact = direct_sum(&positions, &masses, &softening_sq);
+        let approx = forces(&positions, &masses, &softening_sq, 0.0);
+
+        for (a, e) in approx.iter().zip(exact.iter()) {
+            assert!((a - e).norm() <= 1e-9 * e.norm().max(1e-12), "{:?} vs {:?}", a, e);
+        }
+    }
+
+    #[test]
+    fn default_theta_is_accurate() {
+        for seed in 0..5 {
+            let (positions, masses, softening_sq) = cluster(1000, seed);
+            let exact = direct_sum(&positions, &masses, &softening_sq);
+            let mut errors = errors(&forces(&positions, &masses, &softening_sq, DEFAULT_THETA), &exact);
+            errors.sort_by(|a, b| a.total_cmp(b));
+
+            let mean = errors.iter().sum::<f64>() / errors.len() as f64;
+            let worst = errors[errors.len() - 1];
+            assert!(mean < 0.005, "mean error {}", mean);
+            assert!(worst < 0.1, "worst error {}", worst);
+        }
+    }
+
+    #[test]
+    fn potential_energy_matches_direct_sum() {
+        let (positions, masses, softening_sq) = cluster(1000, 7);
+        let exact = direct_potential_energy(&positions, &masses, &softening_sq);
+        let exact_tree = potential_energy(&positions, &masses, &softening_sq, 0.0);
+        let approx = potential_energy(&positions, &masses, &softening_sq, DEFAULT_THETA);
+        assert!(((exact_tree - exact) / exact).abs() < 1e-12, "{} vs {}", exact_tree, exact);
+        assert!(((approx - exact) / exact).abs() < 1e-2, "{} vs {}", approx, exact);
+    }
+
+    #[test]
+    fn coincident_bodies() {
+        // Bodies on top of each other can't be split up by the tree, so shouldn't send it into a loop
+        let positions = vec![Point2::new(1.0, 1.0); 4];
+        let result = forces(&positions, &[1.0; 4], &[1.0; 4], DEFAULT_THETA);
+        assert!(result.iter().all(|f| f.x.is_finite() && f.y.is_finite()));
+    }
+}
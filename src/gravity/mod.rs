@@ -0,0 +1,103 @@
+pub mod barnes_hut;
+
+use ggez::nalgebra as na;
+use na::{Point2, Vector2};
+use serde::{Serialize, Deserialize};
+
+use crate::tools;
+
+// Opening angle used when switching to Barnes-Hut. Lower is more accurate (0 is the same as the direct sum).
+pub const DEFAULT_THETA: f64 = 0.5;
+
+// Softening between two bodies with their own softening lengths (squared). The mean keeps the force
+// between them equal and opposite.
+#[inline]
+pub fn pair_softening_sq(a: f64, b: f64) -> f64 {
+    (a + b) / 2.0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum GravitySolver {
+    #[default]
+    DirectSum,                  // O(N^2), exact.
+    BarnesHut { theta: f64 },   // O(N log N), approximate.
+}
+
+impl GravitySolver {
+    #[inline]
+    pub fn barnes_hut() -> GravitySolver {
+        GravitySolver::BarnesHut { theta: DEFAULT_THETA }
+    }
+
+    // Resultant gravitational force on each body. Output is in the same order as the input.
+    // `softening_sq` is each body's softening length squared.
+    pub fn forces(&self, positions: &[Point2<f64>], masses: &[f64], softening_sq: &[f64]) -> Vec<Vector2<f64>> {
+        match *self {
+            GravitySolver::DirectSum => direct_sum(positions, masses, softening_sq),
+            GravitySolver::BarnesHut { theta } => barnes_hut::forces(positions, masses, softening_sq, theta),
+        }
+    }
+
+    // Total potential energy, softened the same way as the forces. Barnes-Hut approximates it with the
+    // same tree, so it costs about as much as working out the forces.
+    pub fn potential_energy(&self, positions: &[Point2<f64>], masses: &[f64], softening_sq: &[f64]) -> f64 {
+        match *self {
+            GravitySolver::DirectSum => direct_potential_energy(positions, masses, softening_sq),
+            GravitySolver::BarnesHut { theta } => barnes_hut::potential_energy(positions, masses, softening_sq, theta),
+        }
+    }
+
+    #[inline]
+    pub fn accelerations(&self, positions: &[Point2<f64>], masses: &[f64], softening_sq: &[f64]) -> Vec<Vector2<f64>> {
+        // F/m = a
+        self.forces(positions, masses, softening_sq)
+            .into_iter()
+            .zip(masses.iter())
+            .map(|(f, m)| f / *m)
+            .collect()
+    }
+
+    // Swaps between direct sum and Barnes-Hut.
+    pub fn toggled(&self) -> GravitySolver {
+        match *self {
+            GravitySolver::DirectSum => Self::barnes_hut(),
+            GravitySolver::BarnesHut { .. } => GravitySolver::DirectSum,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match *self {
+            GravitySolver::DirectSum => "Direct sum".to_owned(),
+            GravitySolver::BarnesHut { theta } => format!("Barnes-Hut (theta {:.2})", theta),
+        }
+    }
+}
+
+fn direct_sum(positions: &[Point2<f64>], masses: &[f64], softening_sq: &[f64]) -> Vec<Vector2<f64>> {
+    let mut forces = vec![Vector2::new(0.0, 0.0); positions.len()];
+
+    for i in 0..positions.len() {
+        for j in i + 1..positions.len() {
+            let eps_sq = pair_softening_sq(softening_sq[i], softening_sq[j]);
+            let df = tools::newtonian_grav(masses[i], masses[j], &positions[i], &positions[j], eps_sq);
+
+            forces[i] += df;
+            forces[j] -= df; // Equal and opposite force
+        }
+    }
+
+    forces
+}
+
+fn direct_potential_energy(positions: &[Point2<f64>], masses: &[f64], softening_sq: &[f64]) -> f64 {
+    let mut potential = 0.0;
+
+    for i in 0..positions.len() {
+        for j in i + 1..positions.len() {
+            let eps_sq = pair_softening_sq(softening_sq[i], softening_sq[j]);
+            potential += tools::newtonian_potential(masses[i], masses[j], &positions[i], &positions[j], eps_sq);
+        }
+    }
+
+    potential
+}
@@ -0,0 +1,99 @@
+// Runs a scenario without a window (or ggez at all), e.g for recording trajectories on a machine
+// with no display.
+
+use std::time::Instant;
+
+use crate::{
+    cli::{self, CliOptions},
+    gravity::GravitySolver,
+    recorder::{DiagnosticsRecorder, NpzRecorder, TrajectoryRecorder},
+    save::scenario::Scenario,
+    timestep::Timestep,
+    world::World,
+};
+
+pub fn run(opts: &CliOptions) -> Result<(), String> {
+    let (scenario_path, duration) = match (opts.scenario.as_ref(), opts.duration) {
+        (Some(path), Some(duration)) => (path, duration),
+        _ => return Err("--headless needs both --scenario and --duration.".to_owned()),
+    };
+
+    let scenario = Scenario::import(scenario_path)
+        .map_err(|e| format!("Couldn't load {}: {}", scenario_path.display(), e))?;
+    let mut world = World::new();
+    world.set_seed(opts.seed.unwrap_or_else(cli::random_seed));
+    world.settings = scenario.settings;
+    if let Some(tolerance) = opts.tolerance {
+        world.settings.timestep = Timestep::Adaptive { tolerance };
+    }
+    if let Some(theta) = opts.theta {
+        world.settings.gravity_solver = GravitySolver::BarnesHut { theta };
+    }
+    for saved_planet in scenario.bodies.iter() {
+        world.add_existing_body(saved_planet.into());
+    }
+
+    let mut recorder = match opts.record_csv {
+        Some(ref path) => Some(
+            TrajectoryRecorder::start(path, opts.record_interval, &world)
+                .map_err(|e| format!("Couldn't record to {}: {}", path.display(), e))?
+        ),
+        None => None,
+    };
+
+    let mut npz_recorder = match opts.record_npz {
+        Some(ref dir) => Some(
+            NpzRecorder::start(dir, opts.record_interval, &world)
+                .map_err(|e| format!("Couldn't record to {}: {}", dir.display(), e))?
+        ),
+        None => None,
+    };
+
+    let mut diagnostics_recorder = match opts.record_diagnostics {
+        Some(ref path) => Some(
+            DiagnosticsRecorder::start(path, opts.record_interval, &world)
+                .map_err(|e| format!("Couldn't record to {}: {}", path.display(), e))?
+        ),
+        None => None,
+    };
+
+    let dt = 1.0 / opts.physics_hz;
+    let steps = (duration / dt).ceil() as u64;
+    let started = Instant::now();
+
+    println!("Running {} for {}s ({} steps, {} bodies)...", scenario_path.display(), duration, steps, world.body_count());
+    for _ in 0..steps {
+        let merges = world.step(dt);
+        if let Some(ref mut recorder) = recorder {
+            recorder.step(&world, &merges).map_err(|e| format!("Couldn't write trajectory: {}", e))?;
+        }
+        if let Some(ref mut recorder) = npz_recorder {
+            recorder.step(&world).map_err(|e| format!("Couldn't write snapshot: {}", e))?;
+        }
+        if let Some(ref mut recorder) = diagnostics_recorder {
+            recorder.step(&world).map_err(|e| format!("Couldn't write diagnostics: {}", e))?;
+        }
+    }
+    println!(
+        "Simulated {:.2}s in {:.2}s, {} bodies left.",
+        world.time(),
+        started.elapsed().as_secs_f64(),
+        world.body_count(),
+    );
+
+    if let Some(recorder) = recorder {
+        let path = recorder.path().to_owned();
+        let rows = recorder.finish().map_err(|e| format!("Couldn't write trajectory: {}", e))?;
+        println!("Wrote {} rows to {}", rows, path.display());
+    }
+    if let Some(recorder) = diagnostics_recorder {
+        let path = recorder.path().to_owned();
+        let rows = recorder.finish().map_err(|e| format!("Couldn't write diagnostics: {}", e))?;
+        println!("Wrote {} rows of diagnostics to {}", rows, path.display());
+    }
+    if let Some(recorder) = npz_recorder {
+        println!("Wrote {} snapshots to {}", recorder.snapshots(), recorder.dir().display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use crate::save::SaveState;
+
+const MAX_UNDO: usize = 50;
+
+// Undo/redo for anything the user does to the scene. Each entry is the state from just before an
+// action, along with what the action was so it can be reported.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<(String, SaveState)>,
+    redo: Vec<(String, SaveState)>,
+}
+
+impl History {
+    // Call before doing something undoable. Anything that could be redone is lost.
+    pub fn push(&mut self, action: &str, before: SaveState) {
+        if self.undo.len() >= MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.undo.push((action.to_owned(), before));
+        self.redo.clear();
+    }
+
+    // Gives back the state to go to, `current` is kept so it can be redone.
+    pub fn undo(&mut self, current: SaveState) -> Option<(String, SaveState)> {
+        let (action, state) = self.undo.pop()?;
+        self.redo.push((action.clone(), current));
+        Some((action, state))
+    }
+
+    pub fn redo(&mut self, current: SaveState) -> Option<(String, SaveState)> {
+        let (action, state) = self.redo.pop()?;
+        self.undo.push((action.clone(), current));
+        Some((action, state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::WorldSaveData;
+
+    fn state(time: f64) -> SaveState {
+        SaveState { world: WorldSaveData { time, ..Default::default() }, ..Default::default() }
+    }
+
+    #[test]
+    fn undo_then_redo() {
+        let mut history = History::default();
+        history.push("add", state(1.0));
+        history.push("delete", state(2.0));
+
+        let (action, before) = history.undo(state(3.0)).unwrap();
+        assert_eq!((action.as_str(), before.world.time), ("delete", 2.0));
+        let (action, after) = history.redo(before).unwrap();
+        assert_eq!((action.as_str(), after.world.time), ("delete", 3.0));
+        assert!(history.redo(after).is_none());
+    }
+
+    #[test]
+    fn new_checkpoint_clears_redo() {
+        let mut history = History::default();
+        history.push("add", state(1.0));
+        let (_, before) = history.undo(state(2.0)).unwrap();
+
+        history.push("split", before);
+        assert!(history.redo(state(3.0)).is_none());
+        assert_eq!(history.undo(state(3.0)).unwrap().0, "split");
+        assert!(history.undo(state(1.0)).is_none());
+    }
+
+    #[test]
+    fn oldest_undo_is_dropped() {
+        let mut history = History::default();
+        for i in 0..MAX_UNDO + 5 {
+            history.push("add", state(i as f64));
+        }
+        let mut oldest = None;
+        while let Some((_, before)) = history.undo(state(0.0)) {
+            oldest = Some(before.world.time);
+        }
+        assert_eq!(oldest, Some(5.0));
+    }
+}
@@ -0,0 +1,168 @@
+use ggez::nalgebra as na;
+use na::{Point2, Vector2};
+use serde::{Serialize, Deserialize};
+
+// Yoshida 4th order coefficients. w1 = 1/(2 - 2^(1/3)), w0 = -2^(1/3) * w1
+const YOSHIDA_W1: f64 = 1.351_207_191_959_657_8;
+const YOSHIDA_W0: f64 = -1.702_414_383_919_315_3;
+const YOSHIDA_C: [f64; 4] = [
+    YOSHIDA_W1 / 2.0,
+    (YOSHIDA_W0 + YOSHIDA_W1) / 2.0,
+    (YOSHIDA_W0 + YOSHIDA_W1) / 2.0,
+    YOSHIDA_W1 / 2.0,
+];
+const YOSHIDA_D: [f64; 3] = [YOSHIDA_W1, YOSHIDA_W0, YOSHIDA_W1];
+
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum Integrator {
+    SemiImplicitEuler,  // 1st order. What the sim used originally.
+    #[default]
+    Leapfrog,           // Kick-drift-kick, 2nd order.
+    VelocityVerlet,     // 2nd order.
+    Yoshida4,           // 4th order, 3 force evaluations per step.
+}
+
+impl Integrator {
+    // Advances positions and velocities by dt. `accelerations` must give the acceleration of every
+    // body from the given positions, and is only ever called with a complete snapshot.
+    pub fn step<F>(&self, pos: &mut [Point2<f64>], vel: &mut [Vector2<f64>], dt: f64, mut accelerations: F)
+    where
+        F: FnMut(&[Point2<f64>]) -> Vec<Vector2<f64>>,
+    {
+        match *self {
+            Integrator::SemiImplicitEuler => {
+                let acc = accelerations(pos);
+                Self::kick(vel, &acc, dt);
+                Self::drift(pos, vel, dt);
+            }
+            Integrator::Leapfrog => {
+                let acc = accelerations(pos);
+                Self::kick(vel, &acc, dt / 2.0);
+                Self::drift(pos, vel, dt);
+                let acc = accelerations(pos);
+                Self::kick(vel, &acc, dt / 2.0);
+            }
+            Integrator::VelocityVerlet => {
+                let acc = accelerations(pos);
+                for i in 0..pos.len() {
+                    pos[i] += vel[i] * dt + acc[i] * (0.5 * dt * dt);
+                }
+                let new_acc = accelerations(pos);
+                for i in 0..vel.len() {
+                    vel[i] += (acc[i] + new_acc[i]) * (0.5 * dt);
+                }
+            }
+            Integrator::Yoshida4 => {
+                for k in 0..YOSHIDA_D.len() {
+                    Self::drift(pos, vel, YOSHIDA_C[k] * dt);
+                    let acc = accelerations(pos);
+                    Self::kick(vel, &acc, YOSHIDA_D[k] * dt);
+                }
+                Self::drift(pos, vel, YOSHIDA_C[3] * dt);
+            }
+        }
+    }
+
+    #[inline]
+    fn kick(vel: &mut [Vector2<f64>], acc: &[Vector2<f64>], dt: f64) {
+        for (v, a) in vel.iter_mut().zip(acc.iter()) {
+            *v += a * dt;
+        }
+    }
+
+    #[inline]
+    fn drift(pos: &mut [Point2<f64>], vel: &[Vector2<f64>], dt: f64) {
+        for (p, v) in pos.iter_mut().zip(vel.iter()) {
+            *p += v * dt;
+        }
+    }
+
+    // For cycling through with a key.
+    pub fn next(&self) -> Integrator {
+        match *self {
+            Integrator::SemiImplicitEuler => Integrator::Leapfrog,
+            Integrator::Leapfrog => Integrator::VelocityVerlet,
+            Integrator::VelocityVerlet => Integrator::Yoshida4,
+            Integrator::Yoshida4 => Integrator::SemiImplicitEuler,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Integrator::SemiImplicitEuler => "Semi-implicit Euler",
+            Integrator::Leapfrog => "Leapfrog (KDK)",
+            Integrator::VelocityVerlet => "Velocity Verlet",
+            Integrator::Yoshida4 => "Yoshida 4th order",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    // Two unit masses with G = 1, so the tests don't depend on the gravity solvers or their constants.
+    fn accelerations(pos: &[Point2<f64>]) -> Vec<Vector2<f64>> {
+        let d = pos[1] - pos[0];
+        let a = d / d.norm().powi(3);
+        vec![a, -a]
+    }
+
+    fn energy(pos: &[Point2<f64>], vel: &[Vector2<f64>]) -> f64 {
+        0.5 * (vel[0].norm_squared() + vel[1].norm_squared()) - 1.0 / na::distance(&pos[0], &pos[1])
+    }
+
+    // A pair a distance of 1 apart at closest, with `speed` times the circular orbit speed there.
+    fn pair(speed: f64) -> (Vec<Point2<f64>>, Vec<Vector2<f64>>) {
+        let v = speed * 0.5f64.sqrt();
+        (vec![Point2::new(-0.5, 0.0), Point2::new(0.5, 0.0)], vec![Vector2::new(0.0, -v), Vector2::new(0.0, v)])
+    }
+
+    // Runs for `orbits` periods of the circular orbit with `steps` steps each, returning the final state
+    // and the worst relative energy error seen.
+    fn run(integrator: Integrator, speed: f64, orbits: usize, steps: usize) -> (Vec<Point2<f64>>, f64) {
+        let (mut pos, mut vel) = pair(speed);
+        let start = energy(&pos, &vel);
+        let dt = PI * 2.0f64.sqrt() / steps as f64;
+        let mut worst: f64 = 0.0;
+        for _ in 0..orbits * steps {
+            integrator.step(&mut pos, &mut vel, dt, accelerations);
+            worst = worst.max(((energy(&pos, &vel) - start) / start).abs());
+        }
+        (pos, worst)
+    }
+
+    const SYMPLECTIC: [Integrator; 3] = [Integrator::Leapfrog, Integrator::VelocityVerlet, Integrator::Yoshida4];
+
+    #[test]
+    fn circular_orbit() {
+        // Should come back round to where it started after one period
+        for &integrator in SYMPLECTIC.iter() {
+            let (pos, worst) = run(integrator, 1.0, 1, 200);
+            let (start, _) = pair(1.0);
+            for (p, s) in pos.iter().zip(start.iter()) {
+                assert!(na::distance(p, s) < 5e-3, "{}: ended {:?} from start", integrator.name(), p - s);
+            }
+            assert!(worst < 1e-6, "{}: energy error {}", integrator.name(), worst);
+        }
+    }
+
+    #[test]
+    fn energy_error_is_bounded() {
+        // An eccentric orbit, where the error swings about each time round but shouldn't build up
+        for &integrator in SYMPLECTIC.iter() {
+            let (_, early) = run(integrator, 1.2, 10, 500);
+            let (_, late) = run(integrator, 1.2, 100, 500);
+            assert!(early < 1e-3, "{}: energy error {}", integrator.name(), early);
+            assert!(late < early * 1.5, "{}: energy error grew from {} to {}", integrator.name(), early, late);
+        }
+    }
+
+    #[test]
+    fn yoshida_beats_leapfrog() {
+        let (_, leapfrog) = run(Integrator::Leapfrog, 1.2, 10, 500);
+        let (_, yoshida) = run(Integrator::Yoshida4, 1.2, 10, 500);
+        assert!(yoshida * 10.0 < leapfrog, "Yoshida {} vs leapfrog {}", yoshida, leapfrog);
+    }
+}
@@ -3,65 +3,129 @@ mod macros;
 
 mod mouse;
 mod particles;
+mod recorder;
+mod replay;
+mod save;
+mod save_browser;
+mod timeline;
+mod timestep;
 mod body;
+mod camera;
+mod cli;
+mod clock;
+mod collision;
+mod console;
+mod diagnostics;
+mod gravity;
+mod headless;
+mod history;
+mod integrator;
 mod tools;
+mod world;
 
 use ggez::{
     event::{self, KeyCode, KeyMods, MouseButton},
     graphics::{self, DrawMode, DrawParam, Mesh},
-    nalgebra as na, timer, Context, GameResult,
-    filesystem,
+    filesystem, nalgebra as na, timer, Context, GameResult,
 };
 use na::{Point2, Vector2};
-use serde::{Serialize, Deserialize};
 
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::path::Path;
-use std::io::{Read, Write};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use crate::{
     mouse::MouseInfo,
-    body::{Body, BodySaveData, BodyID, planet::PlanetTrail},
+    body::{Body, BodySaveData, BodyID, Mobile, planet::PlanetTrail},
+    camera::{self as cam, Camera, Follow},
+    cli::CliOptions,
+    clock::SimClock,
+    console::{BodyRef, Command, Console},
+    diagnostics::DiagnosticsTracker,
+    gravity::GravitySolver,
+    history::History,
+    recorder::{DiagnosticsRecorder, NpzRecorder, TrajectoryRecorder},
+    replay::{Input, Replay, ReplayPlayer, ReplayRecorder},
+    save::{SaveState, autosave::Autosaver, npz, scenario::Scenario, slots::SaveSlots},
+    save_browser::{BrowserAction, Confirm, SaveBrowser},
+    timeline::{self as tl, Timeline},
+    timestep::Timestep,
+    world::{Merge, World},
 };
 
 pub const TWO_PI: f64 = std::f64::consts::PI * 2.0;
 pub const GRAV_CONSTANT: f64 = 0.001;
 
+const SELECTION_PANEL_WIDTH: f32 = 300.0;
+
+#[allow(dead_code)]
 const PLANET_SPLIT_MOMENTUM_MAG: f64 = 1000000000.0;
 
 struct MainState {
     smoke_sprite_batch: graphics::spritebatch::SpriteBatch,
 
-    planets: HashMap<BodyID, RefCell<Body>>,    // Hashmap of ids
-    planet_trails: HashMap<BodyID, PlanetTrail>,  // Tied to body id. Seperate from body since i may want effect to last after body is removed.
-
-    collided_planets: Vec<BodyID>, // IDs
-    id_counter: BodyID,
+    world: World,
+    clock: SimClock,
+    planet_trails: BTreeMap<BodyID, PlanetTrail>,  // Tied to body id. Seperate from body since i may want effect to last after body is removed.
 
     mouse_info: MouseInfo,
+    camera: Camera,
+    selected: Option<BodyID>,
+    console: Console,
 
     quick_save: Option<SaveState>,
+    slots: SaveSlots,
+    save_browser: SaveBrowser,
+    autosaver: Autosaver,
+    history: History,
+    timeline: Timeline,
+    recorder: Option<TrajectoryRecorder>,
+    npz_recorder: Option<NpzRecorder>,
+    diagnostics_recorder: Option<DiagnosticsRecorder>,
+    record_interval: f64,
+    diagnostics: DiagnosticsTracker,
+
+    held_keys: HashSet<KeyCode>,    // Tracked from input rather than asked for, so replays see the same keys
+    replay_recorder: Option<ReplayRecorder>,
+    replay_player: Option<ReplayPlayer>,
 }
 
 impl MainState {
-    fn new(ctx: &mut Context) -> GameResult<MainState> {
-        let smoke_image = graphics::Image::new(ctx, "/smokeparticle.png").unwrap();
+    fn new(ctx: &mut Context, opts: &CliOptions) -> GameResult<MainState> {
+        let smoke_image = graphics::Image::new(ctx, "/smokeparticle.png")?;
+        let screen = graphics::screen_coordinates(ctx);
 
         let mut s = MainState {
             smoke_sprite_batch: graphics::spritebatch::SpriteBatch::new(smoke_image),
 
-            planets: HashMap::with_capacity(100),
-            planet_trails: HashMap::with_capacity(100),
-            collided_planets: Vec::with_capacity(20),
-
-            id_counter: 0,
+            world: World::new(),
+            clock: SimClock::default(),
+            planet_trails: BTreeMap::new(),
 
             mouse_info: MouseInfo::default(),
+            camera: Camera::new(screen.w, screen.h),
+            selected: None,
+            console: Console::default(),
 
             quick_save: None,
+            slots: SaveSlots::new(filesystem::user_data_dir(ctx), filesystem::user_config_dir(ctx)),
+            save_browser: SaveBrowser::default(),
+            autosaver: Autosaver::start(filesystem::user_data_dir(ctx)),
+            history: History::default(),
+            timeline: Timeline::new(tl::DEFAULT_BUDGET_MB),
+            recorder: None,
+            npz_recorder: None,
+            diagnostics_recorder: None,
+            record_interval: opts.record_interval,
+            diagnostics: DiagnosticsTracker::default(),
+
+            held_keys: HashSet::new(),
+            replay_recorder: None,
+            replay_player: None,
         };
 
+        s.world.set_seed(opts.seed.unwrap_or_else(cli::random_seed));
+
         // s.add_planet(
         //     ctx,
         //     Point2::new(400.0f64, 400.0),
@@ -76,51 +140,632 @@ impl MainState {
 
         //s.spawn_square_of_planets(ctx, Point2::new(50.0, 50.0), 20, 20, 50.0, 5.0);
 
+        s.clock.set_physics_hz(opts.physics_hz);
+        s.clock.max_substeps = opts.max_substeps;
+        if let Some(ref path) = opts.scenario {
+            match Scenario::import(path) {
+                Ok(scenario) => s.load_scenario(&scenario),
+                Err(e) => s.console.show_message(format!("Couldn't load {}: {}", path.display(), e)),
+            }
+        }
+        if let Some(tolerance) = opts.tolerance {
+            s.world.settings.timestep = Timestep::Adaptive { tolerance };
+        }
+        if let Some(theta) = opts.theta {
+            s.world.settings.gravity_solver = GravitySolver::BarnesHut { theta };
+        }
+        if let Some(ref path) = opts.record_csv {
+            s.start_recording(path);
+        }
+        if let Some(ref dir) = opts.record_npz {
+            match NpzRecorder::start(dir, s.record_interval, &s.world) {
+                Ok(recorder) => s.npz_recorder = Some(recorder),
+                Err(e) => s.console.show_message(format!("Couldn't record to {}: {}", dir.display(), e)),
+            }
+        }
+        if let Some(ref path) = opts.record_diagnostics {
+            s.start_diagnostics_recording(path);
+        }
+        if let Some(ref path) = opts.replay {
+            match s.start_playback(path) {
+                Ok(msg) => s.console.show_message(msg),
+                Err(e) => s.console.show_message(format!("Couldn't play {}: {}", path.display(), e)),
+            }
+        }
+
         Ok(s)
     }
 
     #[inline]
     fn add_planet(&mut self, pos: Point2<f64>, vel: Vector2<f64>, radius: f64) {
-        self.add_existing_planet(Body::new(self.id_counter, pos.clone(), vel, radius, 0.0));
+        let id = self.world.add_body(pos, vel, radius);
+        self.add_planet_trail(id, cast_point2_to_f32!(pos));
     }
 
     #[inline]
-    fn add_existing_planet(&mut self, mut pl: Body) {
-        pl.id = self.id_counter;
+    fn add_existing_planet(&mut self, pl: Body) {
         let pos = pl.pos;
-
-        self.planets.insert(
-            self.id_counter,
-            RefCell::new(pl)
-        );
-        self.add_planet_trail(self.id_counter, cast_point2_to_f32!(pos));
-
-        self.id_counter = self.id_counter.wrapping_add(1);
+        let id = self.world.add_existing_body(pl);
+        self.add_planet_trail(id, cast_point2_to_f32!(pos));
     }
 
     fn add_planet_trail(&mut self, id: BodyID, pos: Point2<f32>) {
         self.planet_trails.insert(
             id,
-            PlanetTrail::new(pos),
+            PlanetTrail::new(pos, self.world.stream_seed(id as u64)),
         );
     }
 
-    fn remove_collided_planets(&mut self) {
-        if self.collided_planets.len() > 0 {
-            // Sort out the planet's particle system
-            for key in self.collided_planets.iter() {
-                if let Some(sys) = self.planet_trails.get_mut(key) {
-                    // If the planet no longer exists, then set the particle system to dead.
-                    // If the particle system is dead, it will no longer emit, but will be removed when
-                    // all nodes/particles have faded (see `remove_dead_planet_trails`).
-                    sys.parent_dead = true;
+    fn kill_planet_trails(&mut self, merges: &[Merge]) {
+        for m in merges.iter() {
+            self.kill_planet_trail(m.absorbed);
+        }
+    }
+
+    #[inline]
+    fn kill_planet_trail(&mut self, id: BodyID) {
+        if let Some(sys) = self.planet_trails.get_mut(&id) {
+            // If the planet no longer exists, then set the particle system to dead.
+            // If the particle system is dead, it will no longer emit, but will be removed when
+            // all nodes/particles have faded (see `remove_dead_planet_trails`).
+            sys.parent_dead = true;
+        }
+    }
+
+    fn delete_planet(&mut self, id: BodyID) -> Option<Body> {
+        let removed = self.world.remove_body(id);
+        if let Some(ref body) = removed {
+            self.record_removed(body);
+            self.kill_planet_trail(id);
+            if self.selected == Some(id) {
+                self.selected = None;
+            }
+        }
+        removed
+    }
+
+    // RECORDING //
+    fn start_recording(&mut self, path: &std::path::Path) {
+        match TrajectoryRecorder::start(path, self.record_interval, &self.world) {
+            Ok(recorder) => {
+                self.recorder = Some(recorder);
+                self.console.show_message(format!("Recording trajectories to {}", path.display()));
+            },
+            Err(e) => self.console.show_message(format!("Couldn't record to {}: {}", path.display(), e)),
+        }
+    }
+
+    fn start_diagnostics_recording(&mut self, path: &Path) {
+        match DiagnosticsRecorder::start(path, self.record_interval, &self.world) {
+            Ok(recorder) => {
+                self.diagnostics_recorder = Some(recorder);
+                self.console.show_message(format!("Recording diagnostics to {}", path.display()));
+            },
+            Err(e) => self.console.show_message(format!("Couldn't record to {}: {}", path.display(), e)),
+        }
+    }
+
+    fn stop_diagnostics_recording(&mut self) {
+        if let Some(recorder) = self.diagnostics_recorder.take() {
+            let path = recorder.path().to_owned();
+            match recorder.finish() {
+                Ok(rows) => self.console.show_message(format!("Wrote {} rows to {}", rows, path.display())),
+                Err(e) => self.console.show_message(format!("Couldn't finish writing {}: {}", path.display(), e)),
+            }
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            let path = recorder.path().to_owned();
+            match recorder.finish() {
+                Ok(rows) => self.console.show_message(format!("Wrote {} rows to {}", rows, path.display())),
+                Err(e) => self.console.show_message(format!("Couldn't finish writing {}: {}", path.display(), e)),
+            }
+        }
+    }
+
+    fn toggle_recording(&mut self) {
+        if self.recorder.is_some() {
+            self.stop_recording();
+        } else {
+            self.start_recording(Path::new(&format!("trajectory-{}.csv", tools::unix_time())));
+        }
+    }
+
+    // Stops recording if writing fails, rather than complaining every step.
+    fn handle_record_error(&mut self, result: std::io::Result<()>) {
+        if let Err(e) = result {
+            self.recorder = None;
+            self.console.show_message(format!("Stopped recording: {}", e));
+        }
+    }
+
+    // After loading, undoing etc, so the recordings mark the jump instead of it looking like motion.
+    fn restart_recordings(&mut self) {
+        if let Some(ref mut recorder) = self.recorder {
+            let result = recorder.restart(&self.world);
+            self.handle_record_error(result);
+        }
+        if let Some(ref mut recorder) = self.npz_recorder {
+            if let Err(e) = recorder.restart(&self.world) {
+                self.npz_recorder = None;
+                self.console.show_message(format!("Stopped writing snapshots: {}", e));
+            }
+        }
+        if let Some(ref mut recorder) = self.diagnostics_recorder {
+            if let Err(e) = recorder.restart(&self.world) {
+                self.diagnostics_recorder = None;
+                self.console.show_message(format!("Stopped recording diagnostics: {}", e));
+            }
+        }
+    }
+
+    fn record_removed(&mut self, body: &Body) {
+        if let Some(ref mut recorder) = self.recorder {
+            let result = recorder.removed(self.world.time(), body);
+            self.handle_record_error(result);
+        }
+    }
+
+    fn record_reset(&mut self) {
+        if let Some(ref mut recorder) = self.recorder {
+            let world = &self.world;
+            let result = world
+                .bodies()
+                .values()
+                .try_for_each(|body| recorder.removed(world.time(), &body.borrow()));
+            self.handle_record_error(result);
+        }
+    }
+
+    // INPUT //
+    // Real input from the window. While a replay is playing it is ignored, apart from Escape to stop it.
+    fn user_input(&mut self, input: Input) {
+        if self.replay_player.is_some() {
+            if let Input::KeyDown { key: KeyCode::Escape, .. } = input {
+                self.stop_playback();
+            }
+            return;
+        }
+        // Kept out of the replay itself
+        if let Input::KeyDown { key: KeyCode::F5, .. } = input {
+            self.toggle_replay_recording();
+            return;
+        }
+
+        if let Some(ref mut recorder) = self.replay_recorder {
+            recorder.input(input.clone());
+        }
+        self.handle_input(input);
+    }
+
+    // Everything goes through here, whether it's real or from a replay.
+    fn handle_input(&mut self, input: Input) {
+        match input {
+            Input::KeyDown { key, mods, .. } => {
+                self.held_keys.insert(key);
+                self.handle_key_down(key, KeyMods::from_bits_truncate(mods));
+            },
+            Input::KeyUp { key } => {
+                self.held_keys.remove(&key);
+            },
+            Input::Text(ch) => {
+                if self.console.open {
+                    self.console.push_char(ch);
+                }
+            },
+            Input::MouseDown { button, x, y } => self.handle_mouse_down(button, x, y),
+            Input::MouseUp { button, x, y } => self.handle_mouse_up(button, x, y),
+            Input::MouseMotion { x, y, dx, dy } => self.handle_mouse_motion(x, y, dx, dy),
+            Input::Wheel { y, .. } => self.handle_wheel(y),
+            Input::Loaded(data) => self.replay_load(&data),
+        }
+    }
+
+    fn handle_mouse_down(&mut self, button: MouseButton, x: f32, y: f32) {
+        self.mouse_info.down = true;
+        self.mouse_info.button_down = button;
+        self.mouse_info.down_pos = Point2::new(x, y);
+    }
+
+    fn handle_mouse_up(&mut self, button: MouseButton, x: f32, y: f32) {
+        self.mouse_info.down = false;
+        let origin = self.camera.screen_to_world(&self.mouse_info.down_pos);
+
+        match button {
+            MouseButton::Left => {
+                let clicked = tools::distance_squared_to(&self.mouse_info.down_pos, &Point2::new(x, y)) < 4.0;
+
+                // Click on a body without dragging to select it, otherwise place a planet.
+                match self.world.body_at(&origin, self.camera.pick_radius()) {
+                    Some(id) if clicked => self.selected = Some(id),
+                    _ => {
+                        let release = self.camera.screen_to_world(&Point2::new(x, y));
+                        self.checkpoint("place body");
+                        self.add_planet(origin, origin - release, 5.0);
+                    },
+                }
+            },
+            MouseButton::Middle => {
+                // Follow the clicked body, or stop following if clicked on nothing
+                self.camera.follow = match self.world.body_at(&origin, self.camera.pick_radius()) {
+                    Some(id) => Follow::Body(id),
+                    None => Follow::Free,
+                };
+            },
+            _ => (),
+        }
+    }
+
+    fn handle_mouse_motion(&mut self, x: f32, y: f32, dx: f32, dy: f32) {
+        self.mouse_info.current_drag_position = Point2::new(x, y);
+
+        // Right click + drag to pan
+        if self.mouse_info.down && self.mouse_info.button_down == MouseButton::Right {
+            self.camera.pan(-dx as f64, -dy as f64);
+        }
+    }
+
+    fn handle_wheel(&mut self, y: f32) {
+        let pos = self.mouse_info.current_drag_position;
+        self.camera.zoom_at(&pos, cam::ZOOM_STEP.powf(y as f64));
+    }
+
+    fn handle_key_down(&mut self, key: KeyCode, mods: KeyMods) {
+        if self.autosaver.recovered.is_some() {
+            match key {
+                KeyCode::Y => self.answer_restore_prompt(true),
+                KeyCode::N | KeyCode::Escape => self.answer_restore_prompt(false),
+                _ => (),
+            }
+            return;
+        }
+        if self.console.open {
+            // Typing is handled by `text_input_event`
+            match key {
+                KeyCode::Return | KeyCode::NumpadEnter => self.submit_console(),
+                KeyCode::Back => self.console.backspace(),
+                KeyCode::Escape => self.console.close(),
+                _ => (),
+            }
+            return;
+        }
+        if self.save_browser.open {
+            if let Some(action) = self.save_browser.key_down(key) {
+                self.run_browser_action(action);
+            }
+            return;
+        }
+
+        match key {
+            KeyCode::Return => self.console.open(),
+            KeyCode::B => self.save_browser.open(self.slots.list()),
+            KeyCode::Delete => {
+                if let Some(id) = self.selected {
+                    self.checkpoint("delete");
+                    self.delete_planet(id);
+                }
+            },
+            KeyCode::R => {
+                self.checkpoint("reset");
+                self.record_reset();
+                self.clear_planets();
+                if mods.contains(KeyMods::CTRL) {       // CTRL + R to clear planets AND save
+                    self.quick_save = None;
+                }
+            },
+            KeyCode::S => {
+                if mods.contains(KeyMods::CTRL) {
+                    self.console.open_with("save ");
+                } else {
+                    self.save_to_quick_save();
                 }
+            },
+            KeyCode::L => {
+                if mods.contains(KeyMods::CTRL) {
+                    self.save_browser.open(self.slots.list());
+                } else {
+                    self.load_from_quick_save();
+                }
+            },
+            KeyCode::Z if mods.contains(KeyMods::CTRL) => {
+                if mods.contains(KeyMods::SHIFT) {
+                    self.redo();
+                } else {
+                    self.undo();
+                }
+            },
+            KeyCode::Y if mods.contains(KeyMods::CTRL) => self.redo(),
+            KeyCode::G => {
+                self.checkpoint("gravity change");
+                self.world.settings.gravity_solver = self.world.settings.gravity_solver.toggled();
+            },
+            KeyCode::I => {
+                self.checkpoint("integrator change");
+                self.world.settings.integrator = self.world.settings.integrator.next();
+            },
+            KeyCode::A => {
+                self.checkpoint("timestep change");
+                self.world.settings.timestep = self.world.settings.timestep.toggled();
+            },
+            KeyCode::H => self.clock.cycle_physics_hz(),
+            KeyCode::T if self.replay_player.is_none() => self.toggle_recording(),
+            KeyCode::Space => self.clock.toggle_pause(),
+            KeyCode::Home => self.camera.reset(),
+            KeyCode::Escape => self.selected = None,
+            KeyCode::C => {
+                self.camera.follow = if self.camera.follow == Follow::CentreOfMass {
+                    Follow::Free
+                } else {
+                    Follow::CentreOfMass
+                };
+            },
+            KeyCode::Period => self.clock.single_step(),
+            KeyCode::LBracket => self.scrub_timeline(-1),
+            KeyCode::RBracket => self.scrub_timeline(1),
+            KeyCode::Equals | KeyCode::Add => self.clock.speed_up(),
+            KeyCode::Minus | KeyCode::Subtract => self.clock.slow_down(),
+            _ => ()
+        }
+    }
+
+    // REPLAYS //
+    // Anything that changes what input does is reset, so recording and playback start off the same.
+    fn reset_for_replay(&mut self, initial: &SaveState) {
+        self.console.close();
+        self.save_browser.close();
+        self.autosaver.recovered = None;
+        self.history = History::default();
+        self.timeline.clear();
+        self.quick_save = None;
+        self.mouse_info = MouseInfo::default();
+        self.held_keys.clear();
+        self.load_from_save_state(initial);
+    }
+
+    fn toggle_replay_recording(&mut self) {
+        if self.replay_recorder.is_some() {
+            self.stop_replay_recording();
+        } else {
+            let initial = self.save_state();
+            self.reset_for_replay(&initial);
+            self.replay_recorder = Some(ReplayRecorder::new(initial));
+            self.console.show_message("Recording a replay, F5 to stop.");
+        }
+    }
+
+    fn stop_replay_recording(&mut self) {
+        if let Some(recorder) = self.replay_recorder.take() {
+            let frames = recorder.frames();
+            let replay = recorder.finish(&self.world);
+            let path = PathBuf::from(format!("replay-{}.orbrep", tools::unix_time()));
+
+            match replay.save_to_file(&path) {
+                Ok(()) => self.console.show_message(format!("Saved a replay of {} frames to {}", frames, path.display())),
+                Err(e) => self.console.show_message(format!("Couldn't save replay to {}: {}", path.display(), e)),
             }
+        }
+    }
+
+    fn start_playback(&mut self, path: &Path) -> Result<String, String> {
+        let replay = Replay::load_from_file(path).map_err(|e| e.to_string())?;
+        self.stop_replay_recording();
+
+        let player = ReplayPlayer::new(replay);
+        self.reset_for_replay(player.initial());
+        self.replay_player = Some(player);
+
+        Ok(format!("Playing {}, Escape to stop.", path.display()))
+    }
+
+    fn stop_playback(&mut self) {
+        if self.replay_player.take().is_some() {
+            self.console.show_message("Stopped the replay.");
+        }
+    }
+
+    // Loading from disk depends on what files are there, so replays keep what a load left instead of
+    // loading again when played back.
+    fn record_loaded(&mut self) {
+        if self.replay_recorder.is_none() {
+            return;
+        }
+        match self.save_state().encode() {
+            Ok(data) => self.replay_recorder.as_mut().unwrap().input(Input::Loaded(data)),
+            Err(e) => self.console.show_message(format!("Couldn't add the load to the replay: {}", e)),
+        }
+    }
+
+    fn replay_load(&mut self, data: &[u8]) {
+        match SaveState::decode(data) {
+            Ok((_, state)) => {
+                self.save_browser.close();  // Loading from the browser closes it
+                self.checkpoint("load");
+                self.timeline.clear();
+                self.load_from_save_state(&state);
+            },
+            Err(e) => self.console.show_message(format!("Couldn't load the replay's save: {}", e)),
+        }
+    }
+
+    // Handles the inputs due before this frame, and gives the recorded frame time to use instead of
+    // the real one. None once the replay is over.
+    fn play_replay_frame(&mut self) -> Option<f64> {
+        let player = self.replay_player.as_mut()?;
+        let inputs = player.take_inputs();
+        let frame_dt = player.next_frame_dt();
+
+        for input in inputs {
+            self.handle_input(input);
+        }
+
+        if frame_dt.is_none() {
+            if let Some(player) = self.replay_player.take() {
+                self.console.show_message(if player.matches(&self.world) {
+                    "Replay finished, and ended up exactly where the recording did."
+                } else {
+                    "Replay finished, but ended up somewhere different to the recording!"
+                });
+            }
+        }
+        frame_dt
+    }
+
+    // COMMANDS //
+    fn resolve_body(&self, body: &BodyRef) -> Result<BodyID, String> {
+        match *body {
+            BodyRef::Id(id) if self.world.bodies().contains_key(&id) => Ok(id),
+            BodyRef::Id(id) => Err(format!("No body with id {}.", id)),
+            BodyRef::Name(ref name) => self.world
+                .find_by_name(name)
+                .ok_or_else(|| format!("No body named \"{}\".", name)),
+        }
+    }
+
+    fn run_command(&mut self, command: Command) -> Result<String, String> {
+        // A replay could have come from anyone. Loads are in it as what they loaded (see `record_loaded`).
+        if self.replay_player.is_some() && command.uses_files() {
+            return Ok(String::new());
+        }
+
+        match command {
+            Command::Rename { body, name } => {
+                let id = self.resolve_body(&body)?;
+                let name = name.trim().to_owned();
+                if name.is_empty() {
+                    return Err("Name can't be empty.".to_owned());
+                }
+                if self.world.find_by_name(&name).is_some_and(|other| other != id) {
+                    return Err(format!("\"{}\" is already taken.", name));
+                }
+
+                self.checkpoint("rename");
+                let name = self.world.rename_body(id, name).unwrap_or_default();
+                Ok(format!("Renamed body {} to \"{}\".", id, name))
+            },
+            Command::Find(pattern) => {
+                let found = self.world.search_names(&pattern);
+                let first = *found.first().ok_or_else(|| format!("Nothing matching \"{}\".", pattern))?;
+
+                // Select and look at the first match
+                self.selected = Some(first);
+                self.camera.follow = Follow::Free;
+                self.camera.pos = self.world.bodies()[&first].borrow().pos;
+
+                let labels: Vec<String> = found.iter().map(|id| self.world.bodies()[id].borrow().label()).collect();
+                Ok(format!("Found: {}", labels.join(", ")))
+            },
+            Command::Delete(body) => {
+                let id = self.resolve_body(&body)?;
+                self.checkpoint("delete");
+                let removed = self.delete_planet(id).unwrap();
+                Ok(format!("Deleted {}.", removed.label()))
+            },
+            Command::Colour { body, colour } => {
+                let id = self.resolve_body(&body)?;
+                self.checkpoint("colour change");
+                self.world.bodies()[&id].borrow_mut().colour = colour;
+                Ok(format!("Changed colour of body {}.", id))
+            },
+            Command::Softening(length) => {
+                if let Some(length) = length {
+                    self.checkpoint("softening change");
+                    self.world.settings.softening = length;
+                }
+                Ok(format!("Softening length: {}", self.world.settings.softening))
+            },
+            Command::BodySoftening { body, length } => {
+                let id = self.resolve_body(&body)?;
+                self.checkpoint("softening change");
+                self.world.bodies()[&id].borrow_mut().softening = length;
+                Ok(match length {
+                    Some(length) => format!("Body {} is now softened by {}.", id, length),
+                    None => format!("Body {} now uses the global softening.", id),
+                })
+            },
+            Command::Import(path) => {
+                let scenario = Scenario::import(&path).map_err(|e| e.to_string())?;
+                self.checkpoint("import");
+                self.timeline.clear();
+                self.load_scenario(&scenario);
+                self.record_loaded();
+                Ok(format!("Imported {} bodies from {}", scenario.bodies.len(), path.display()))
+            },
+            Command::Npz(path) => {
+                let bodies = Scenario::from_world(&self.world).bodies;
+                npz::export(&path, self.world.time(), &bodies).map_err(|e| e.to_string())?;
+                Ok(format!("Wrote a snapshot of {} bodies to {}", bodies.len(), path.display()))
+            },
+            Command::Diagnostics(Some(path)) => {
+                self.stop_diagnostics_recording();
+                self.start_diagnostics_recording(&path);
+                Ok(String::new())
+            },
+            Command::Diagnostics(None) => {
+                if self.diagnostics_recorder.is_none() {
+                    return Err("Not recording diagnostics.".to_owned());
+                }
+                self.stop_diagnostics_recording();
+                Ok(String::new())
+            },
+            Command::Export(path) => {
+                Scenario::from_world(&self.world).export(&path).map_err(|e| e.to_string())?;
+                Ok(format!("Exported to {}", path.display()))
+            },
+            Command::Save(name) => self.save_to_slot(&name, false),
+            Command::Load(name) => self.load_from_slot(&name),
+            Command::Saves => {
+                self.save_browser.open(self.slots.list());
+                Ok(String::new())
+            },
+            Command::Timeline(budget) => {
+                if let Some(mb) = budget {
+                    self.timeline.set_budget_mb(mb);
+                }
+                Ok(format!(
+                    "Timeline: {} keyframes, {:.1} of {} MB.",
+                    self.timeline.keyframe_count(),
+                    self.timeline.megabytes(),
+                    self.timeline.budget_mb(),
+                ))
+            },
+            Command::Replay(path) => self.start_playback(&path),
+            Command::Tolerance(tolerance) => {
+                if let Some(tolerance) = tolerance {
+                    self.checkpoint("timestep change");
+                    self.world.settings.timestep = tolerance.map_or(Timestep::Fixed, |tolerance| Timestep::Adaptive { tolerance });
+                }
+                Ok(format!("Timestep: {}", self.world.settings.timestep.name()))
+            },
+            Command::Theta(theta) => {
+                if let Some(theta) = theta {
+                    self.checkpoint("gravity change");
+                    self.world.settings.gravity_solver = theta.map_or(GravitySolver::DirectSum, |theta| GravitySolver::BarnesHut { theta });
+                }
+                Ok(format!("Gravity: {}", self.world.settings.gravity_solver.name()))
+            },
+            Command::Substeps(max) => {
+                if let Some(max) = max {
+                    self.clock.max_substeps = max;
+                }
+                Ok(format!("Max physics steps per frame: {} (times the time scale)", self.clock.max_substeps))
+            },
+            Command::Help => Ok(console::HELP.to_owned()),
+        }
+    }
 
-            let temp_c = self.collided_planets.clone();
-            self.planets.retain(|key, _| !temp_c.contains(&key));
+    fn submit_console(&mut self) {
+        let result = match self.console.submit() {
+            Some(Ok(command)) => self.run_command(command),
+            Some(Err(e)) => Err(e),
+            None => return,
+        };
 
-            self.collided_planets.clear();
+        match result {
+            Ok(ref msg) if msg.is_empty() => (),
+            Ok(msg) => self.console.show_message(msg),
+            Err(e) => self.console.show_message(format!("Error: {}", e)),
         }
     }
 
@@ -131,15 +776,6 @@ impl MainState {
             .retain(|_, sys| !sys.parent_dead || sys.particle_count() > 0 || sys.node_count() > 1);
     }
 
-    fn is_colliding(p1: &Point2<f64>, p2: &Point2<f64>, r1: f64, r2: f64) -> bool {
-        Self::aabb(p1, p2, r1, r2) && tools::distance_squared_to(p1, p2) <= (r1 + r2).powi(2)
-    }
-
-    fn aabb(p1: &Point2<f64>, p2: &Point2<f64>, r1: f64, r2: f64) -> bool {
-        let total_rad = r1 + r2;
-        p2.x - p1.x <= total_rad && p2.y - p1.y <= total_rad
-    }
-
     fn get_total_particle_count(&self) -> usize {
         let mut count = 0;
         for (_, p) in self.planet_trails.iter() {
@@ -148,6 +784,20 @@ impl MainState {
         count
     }
 
+    // Arrow keys
+    fn pan_camera_with_keys(&mut self, dt: f64) {
+        let mut dir = Vector2::new(0.0, 0.0);
+        if self.held_keys.contains(&KeyCode::Left) { dir.x -= 1.0; }
+        if self.held_keys.contains(&KeyCode::Right) { dir.x += 1.0; }
+        if self.held_keys.contains(&KeyCode::Up) { dir.y -= 1.0; }
+        if self.held_keys.contains(&KeyCode::Down) { dir.y += 1.0; }
+
+        if dir.x != 0.0 || dir.y != 0.0 {
+            dir *= cam::KEY_PAN_SPEED * dt;
+            self.camera.pan(dir.x, dir.y);
+        }
+    }
+
     #[inline]
     fn draw_fake_planet(&self, ctx: &mut Context, pos: Point2<f32>, rad: f32) -> GameResult {
         let circ = Mesh::new_circle(
@@ -164,9 +814,78 @@ impl MainState {
         Ok(())
     }
 
+    fn timestep_info(&self) -> String {
+        let (substep, substeps) = self.world.last_substep();
+        match self.world.settings.timestep {
+            Timestep::Fixed => format!("fixed, {:.2} ms", self.clock.step_dt() * 1000.0),
+            Timestep::Adaptive { tolerance } if substeps > 1 => format!(
+                "adaptive ({}), {:.3} ms x{}", tolerance, substep * 1000.0, substeps
+            ),
+            Timestep::Adaptive { tolerance } => format!("adaptive ({}), {:.2} ms", tolerance, self.clock.step_dt() * 1000.0),
+        }
+    }
+
+    fn diagnostics_info(&self) -> String {
+        let (d, start, drift) = match (self.diagnostics.latest(), self.diagnostics.start(), self.diagnostics.drift()) {
+            (Some(d), Some(start), Some(drift)) if self.world.body_count() > 0 => (d, start, drift),
+            _ => return String::new(),
+        };
+        let show_drift = |drift: Option<f64>| drift.map(|v| format!(" (drift {:+.2e})", v)).unwrap_or_default();
+
+        format!(
+            "\nEnergy: {:.4e} (KE {:.3e}, PE {:.3e}){}\nLost to collisions: {:.3e}\nMomentum: ({:.3e}, {:.3e}){}\nAngular momentum: {:.4e}{}",
+            d.total_energy(),
+            d.kinetic,
+            d.potential,
+            show_drift(drift.energy),
+            d.collision_energy - start.collision_energy,
+            d.momentum.x,
+            d.momentum.y,
+            show_drift(drift.momentum),
+            d.angular_momentum,
+            show_drift(drift.angular_momentum),
+        )
+    }
+
     fn draw_fps_and_info(&self, ctx: &mut Context) -> GameResult {
         use graphics::Text;
-        let text = Text::new(format!("{:.2}\nBodies: {}\nParticles: {}", timer::fps(ctx), self.planets.len(), self.get_total_particle_count()));
+        let text = Text::new(format!(
+            "{:.2}\nTime: {:.2}s (x{}){}\nTimeline: {} keyframes ({:.1}/{} MB){}\nBodies: {}\nParticles: {}\nGravity: {}\nIntegrator: {}\nPhysics: {:.0} Hz\nTimestep: {}\nSoftening: {}\nSeed: {}{}{}{}{}",
+            timer::fps(ctx),
+            self.world.time(),
+            self.clock.time_scale(),
+            if self.clock.paused { " PAUSED" } else { "" },
+            self.timeline.keyframe_count(),
+            self.timeline.megabytes(),
+            self.timeline.budget_mb(),
+            if self.timeline.is_scrubbing() { " SCRUBBING" } else { "" },
+            self.world.body_count(),
+            self.get_total_particle_count(),
+            self.world.settings.gravity_solver.name(),
+            self.world.settings.integrator.name(),
+            self.clock.physics_hz(),
+            self.timestep_info(),
+            self.world.settings.softening,
+            self.world.seed(),
+            match self.camera.follow {
+                Follow::Free => String::new(),
+                Follow::Body(id) => format!("\nFollowing: body {}", id),
+                Follow::CentreOfMass => "\nFollowing: centre of mass".to_owned(),
+            },
+            match self.recorder {
+                Some(ref recorder) => format!("\nRecording: {} rows", recorder.rows()),
+                None => String::new(),
+            },
+            self.diagnostics_info(),
+            match (&self.replay_recorder, &self.replay_player) {
+                (Some(recorder), _) => format!("\nRecording replay: {} frames", recorder.frames()),
+                (_, Some(player)) => {
+                    let (frame, frames) = player.progress();
+                    format!("\nReplaying: frame {}/{}", frame, frames)
+                },
+                _ => String::new(),
+            },
+        ));
 
         graphics::draw(
             ctx,
@@ -176,6 +895,59 @@ impl MainState {
         Ok(())
     }
 
+    fn draw_selection_info(&self, ctx: &mut Context, id: BodyID) -> GameResult {
+        use graphics::Text;
+        let pl = match self.world.bodies().get(&id) {
+            Some(rc) => rc.borrow(),
+            None => return Ok(()),
+        };
+        let vel = pl.vel();
+
+        let text = Text::new(format!(
+            "{}\nMass: {:.3e}\nRadius: {:.2}\nPosition: ({:.1}, {:.1})\nVelocity: ({:.2}, {:.2})\nSpeed: {:.2}\nKinetic energy: {:.3e}\nSoftening: {}\nDominant attractor: {}",
+            pl.label(),
+            pl.mass,
+            pl.radius,
+            pl.pos.x, pl.pos.y,
+            vel.x, vel.y,
+            vel.norm(),
+            pl.kinetic_energy(),
+            match pl.softening {
+                Some(length) => length.to_string(),
+                None => format!("{} (global)", self.world.settings.softening),
+            },
+            match self.world.dominant_attractor(id) {
+                Some(other) => self.world.bodies()[&other].borrow().label(),
+                None => "none".to_owned(),
+            },
+        ));
+
+        let screen = self.camera.screen_rect();
+        graphics::draw(
+            ctx,
+            &text,
+            DrawParam::default().dest(Point2::new(screen.w - SELECTION_PANEL_WIDTH, 10.0)),
+        )?;
+        Ok(())
+    }
+
+    fn draw_restore_prompt(&self, ctx: &mut Context) -> GameResult {
+        use graphics::Text;
+        let text = Text::new("Orbits didn't close properly last time. Restore the last autosave? (Y/N)");
+        let screen = self.camera.screen_rect();
+        let width = text.width(ctx) as f32;
+
+        graphics::draw(
+            ctx,
+            &text,
+            DrawParam::default()
+                .dest(Point2::new((screen.w - width) / 2.0, screen.h / 2.0))
+                .color([1.0, 0.85, 0.3, 1.0].into()),
+        )?;
+        Ok(())
+    }
+
+    #[allow(dead_code)]
     fn spawn_square_of_planets(
         &mut self,
         top_left: Point2<f64>,
@@ -198,12 +970,10 @@ impl MainState {
     // CLEARING //
     #[inline]
     fn clear_planets(&mut self) {
-        self.planets.clear();
+        self.world.clear();
         for (_, sys) in self.planet_trails.iter_mut() {
             sys.parent_dead = true;
         }
-        self.collided_planets.clear();
-        self.id_counter = 0;
     }
 
     #[inline]
@@ -212,11 +982,10 @@ impl MainState {
     }
 
     #[inline]
+    #[allow(dead_code)]
     fn clear_planets_and_trails(&mut self) {
         self.planet_trails.clear();
-        self.planets.clear();
-        self.collided_planets.clear();
-        self.id_counter = 0;
+        self.world.clear();
     }
     
     #[inline]
@@ -226,31 +995,90 @@ impl MainState {
     }
 
     // SAVING //
-    fn save_to_file(&self, ctx: &mut Context, path: &Path) -> GameResult {
-        println!("Saving: {}", path.display());
-        let save = SaveState::new_from_main_state(&self);
-        let encoded = bincode::serialize(&save).unwrap();
-
-        let mut file = filesystem::create(ctx, path)?;
-        file.write(encoded.as_slice())?;
+    // Asks first if the slot is already taken, unless `overwrite`.
+    fn save_to_slot(&mut self, name: &str, overwrite: bool) -> Result<String, String> {
+        if !overwrite && self.slots.exists(name) {
+            self.save_browser.open_with_confirm(self.slots.list(), Confirm::Overwrite(name.to_owned()));
+            return Ok(format!("\"{}\" already exists.", name));
+        }
 
-        Ok(())
+        println!("Saving: {}", name);
+        self.slots.save(name, &self.save_state()).map_err(|e| e.to_string())?;
+        if self.save_browser.open {
+            self.save_browser.set_slots(self.slots.list());
+        }
+        Ok(format!("Saved \"{}\".", name))
     }
 
-    fn load_from_file(&mut self, ctx: &mut Context, path: &Path) -> GameResult {
-        println!("Loading: {}", path.display());
-        let save_state = SaveState::load_from_file(ctx, path)?;
+    fn load_from_slot(&mut self, name: &str) -> Result<String, String> {
+        println!("Loading: {}", name);
+        let save_state = self.slots.load(name).map_err(|e| e.to_string())?;
+        self.checkpoint("load");
+        self.timeline.clear();
         self.load_from_save_state(&save_state);
-        Ok(())
+        self.record_loaded();
+        Ok(format!("Loaded \"{}\".", name))
+    }
+
+    fn answer_restore_prompt(&mut self, restore: bool) {
+        if !restore {
+            self.autosaver.recovered = None;
+            return;
+        }
+
+        match self.autosaver.load_recovered() {
+            Ok(save_state) => {
+                self.checkpoint("restore autosave");
+                self.timeline.clear();
+                self.load_from_save_state(&save_state);
+                self.console.show_message("Restored the last autosave.");
+            },
+            Err(e) => self.console.show_message(format!("Couldn't restore the autosave: {}", e)),
+        }
+    }
+
+    fn run_browser_action(&mut self, action: BrowserAction) {
+        if self.replay_player.is_some() {
+            return;     // Same as `run_command`
+        }
+        let result = match action {
+            BrowserAction::Load(name) => {
+                let result = self.load_from_slot(&name);
+                if result.is_ok() {
+                    self.save_browser.close();
+                }
+                result
+            },
+            BrowserAction::Overwrite(name) => self.save_to_slot(&name, true),
+            BrowserAction::Delete(name) => self.slots
+                .delete(&name)
+                .map(|()| {
+                    self.save_browser.set_slots(self.slots.list());
+                    format!("Deleted \"{}\".", name)
+                })
+                .map_err(|e| e.to_string()),
+        };
+
+        match result {
+            Ok(msg) => self.console.show_message(msg),
+            Err(e) => self.console.show_message(format!("Error: {}", e)),
+        }
+    }
+
+    #[inline]
+    fn save_state(&self) -> SaveState {
+        SaveState::new(&self.world, &self.clock, &self.camera, &self.planet_trails)
     }
 
     fn save_to_quick_save(&mut self) {
         println!("Saving to temporary save.");
-        self.quick_save = Some(SaveState::new_from_main_state(&self));
+        self.quick_save = Some(self.save_state());
     }
 
     fn load_from_quick_save(&mut self) {
         println!("Loading from temporary save.");
+        self.checkpoint("quick load");
+        self.timeline.clear();
         let temp = self.quick_save.take();
         match temp {
             None => self.clear_all(),
@@ -262,216 +1090,349 @@ impl MainState {
         self.quick_save = temp;
     }
 
+    // UNDO //
+    // The camera and clock are left out, undoing shouldn't move the view or unpause. So the physics
+    // rate and max substeps (H, `substeps`) aren't undone, but the simulation settings are, so changing
+    // one of those is a checkpoint too.
+    fn history_snapshot(&self) -> SaveState {
+        let mut snapshot = self.save_state();
+        snapshot.camera = None;
+        snapshot.clock = None;
+        snapshot
+    }
+
+    // Call just before changing the scene in a way the user might want to undo. Changing things
+    // while scrubbing the timeline branches off from there.
+    fn checkpoint(&mut self, action: &str) {
+        self.timeline.branch();
+        self.diagnostics.reset();
+        let snapshot = self.history_snapshot();
+        self.history.push(action, snapshot);
+    }
+
+    fn undo(&mut self) {
+        match self.history.undo(self.history_snapshot()) {
+            Some((action, state)) => {
+                self.restore_snapshot(&state);
+                self.console.show_message(format!("Undid {}.", action));
+            },
+            None => self.console.show_message("Nothing to undo."),
+        }
+    }
+
+    fn redo(&mut self) {
+        match self.history.redo(self.history_snapshot()) {
+            Some((action, state)) => {
+                self.restore_snapshot(&state);
+                self.console.show_message(format!("Redid {}.", action));
+            },
+            None => self.console.show_message("Nothing to redo."),
+        }
+    }
+
+    // Like `load_from_save_state`, but keeps the selection if the body is still there.
+    fn restore_snapshot(&mut self, state: &SaveState) {
+        let selected = self.selected;
+        self.load_from_save_state(state);
+        self.selected = selected.filter(|id| self.world.bodies().contains_key(id));
+    }
+
+    // TIMELINE //
+    // Trails are left out to keep keyframes small, they just start again when scrubbing.
+    fn keyframe_state(&self) -> SaveState {
+        let mut keyframe = self.history_snapshot();
+        keyframe.trails.clear();
+        keyframe
+    }
+
+    fn record_keyframe(&mut self) {
+        let keyframe = self.keyframe_state();
+        if let Err(e) = self.timeline.record(self.world.time(), &keyframe) {
+            eprintln!("Couldn't record timeline keyframe: {}", e);
+        }
+    }
+
+    fn scrub_timeline(&mut self, by: isize) {
+        if !self.clock.paused {
+            self.console.show_message("Pause (Space) to scrub through the timeline.");
+            return;
+        }
+        if !self.timeline.is_scrubbing() {
+            self.record_keyframe();    // So the present can be scrubbed back to
+        }
+
+        match self.timeline.scrub(by) {
+            Some(Ok(state)) => self.restore_snapshot(&state),
+            Some(Err(e)) => self.console.show_message(format!("Couldn't load keyframe: {}", e)),
+            None => (),
+        }
+    }
+
     fn load_planet(&mut self, saved_planet: &BodySaveData) {
         println!("Loading planet.");
-        let mut loaded_planet: Body = saved_planet.into();
-        let new_id = self.id_counter;
-        self.id_counter += 1;
-        loaded_planet.id = new_id;
-
-        self.planets.insert(new_id, RefCell::new(loaded_planet));
-        self.add_planet_trail(new_id, Point2::new(saved_planet.pos_x as f32, saved_planet.pos_y as f32));
+        self.add_existing_planet(saved_planet.into());
     }
 
-    #[inline]
     fn load_from_save_state(&mut self, save: &SaveState) {
         self.clear_all();
+        self.selected = None;
+        self.diagnostics.reset();
+
+        self.world.restore(&save.world);
+        if let Some(ref clock) = save.clock {
+            self.clock.restore(clock);
+        }
+        if let Some(ref camera) = save.camera {
+            self.camera.restore(camera);
+        }
         self.load_planets_from_save_state(save);
+
+        // Older saves have no trails, so give every body a fresh one.
+        if save.trails.is_empty() {
+            for (id, body) in self.world.bodies().iter() {
+                let trail = PlanetTrail::new(cast_point2_to_f32!(body.borrow().pos), self.world.stream_seed(*id as u64));
+                self.planet_trails.insert(*id, trail);
+            }
+        } else {
+            self.planet_trails = save.trails
+                .iter()
+                .map(|(id, trail)| (*id, PlanetTrail::from_save_data(trail)))
+                .collect();
+        }
+        self.restart_recordings();
+    }
+
+    // Starts the scenario from time 0, like loading a save does.
+    fn load_scenario(&mut self, scenario: &Scenario) {
+        self.clear_all();
+        self.selected = None;
+        self.diagnostics.reset();
+        self.world.settings = scenario.settings;
+        for saved_planet in scenario.bodies.iter() {
+            self.load_planet(saved_planet);
+        }
+        self.restart_recordings();
     }
 
+    // Keeps the saved ids, so that trails, the camera and the id counter still line up.
     #[inline]
     fn load_planets_from_save_state(&mut self, save: &SaveState) {
         for (_, saved_planet) in save.planets.iter() {
-            self.load_planet(saved_planet);
+            self.world.insert_body(saved_planet.into());
         }
     }
 }
 
 impl event::EventHandler for MainState {
     fn update(&mut self, ctx: &mut Context) -> GameResult {
-        let dt = timer::duration_to_f64(timer::delta(ctx));
-        let time_since_start = timer::time_since_start(ctx);
+        let mut frame_dt = timer::duration_to_f64(timer::delta(ctx));
+        if self.replay_player.is_some() {
+            match self.play_replay_frame() {
+                Some(dt) => frame_dt = dt,
+                None => return Ok(()),
+            }
+        }
+        self.pan_camera_with_keys(frame_dt);
 
         //println!("Particles: {}", self.get_total_particle_count());
 
         self.remove_dead_planet_trails();
-        self.remove_collided_planets();
-        // let mut planets_to_add: Vec<Body> = vec![];
-
-        let keys: Vec<&u32> = self.planets.keys().collect();
-
-        for i in 0..keys.len() {
-            // For each planet
-            let mut me = self.planets.get(keys[i]).unwrap().borrow_mut();
-            for j in i + 1..keys.len() {
-                // For every other planet
-                let mut other = self.planets.get(keys[j]).unwrap().borrow_mut();
-
-                if Self::is_colliding(&me.pos, &other.pos, me.radius, other.radius) {
-                    //self.planets.remove(keys[j]);
-                    if me.radius < other.radius {
-                        // let pl = other.split(0.9, self.id_counter, Vector2::new(PLANET_SPLIT_MOMENTUM_MAG, 0.0), std::f64::consts::PI);
-                        other.collide(&me);
-                        self.collided_planets.push(*keys[i]);
-                        // pl
-                    } else {
-                        // let pl = me.split(0.9, self.id_counter, Vector2::new(PLANET_SPLIT_MOMENTUM_MAG, 0.0), std::f64::consts::PI);
-                        me.collide(&other);
-                        self.collided_planets.push(*keys[j]);
-                        // pl
-                    }
-                    //planets_to_add.push(split_pl);
-                } else {
-                    let df1 = tools::newtonian_grav(me.mass, other.mass, &me.pos, &other.pos);
 
-                    me.res_force += df1;
-                    other.res_force -= df1; // Equal and opposite force
+        // A replay shouldn't replace the user's own autosave
+        if self.autosaver.due(frame_dt) && self.world.body_count() > 0 && self.replay_player.is_none() {
+            self.autosaver.save(self.save_state());
+        }
+
+        let steps = self.clock.advance(frame_dt);
+        if steps > 0 {
+            self.timeline.branch();
+        }
+        for _ in 0..steps {
+            let merges = self.world.step(self.clock.step_dt());
+            self.kill_planet_trails(&merges);
+            self.camera.follow_merges(&merges);
+            self.selected = self.selected.map(|id| world::survivor_of(id, &merges));
+
+            if let Some(ref mut recorder) = self.recorder {
+                let result = recorder.step(&self.world, &merges);
+                self.handle_record_error(result);
+            }
+            if let Some(ref mut recorder) = self.npz_recorder {
+                if let Err(e) = recorder.step(&self.world) {
+                    self.npz_recorder = None;
+                    self.console.show_message(format!("Stopped writing snapshots: {}", e));
                 }
             }
-            me.update_physics(dt);
+            if let Some(ref mut recorder) = self.diagnostics_recorder {
+                if let Err(e) = recorder.step(&self.world) {
+                    self.diagnostics_recorder = None;
+                    self.console.show_message(format!("Stopped recording diagnostics: {}", e));
+                }
+            }
+        }
+        self.diagnostics.update(&self.world, frame_dt);
+        if self.timeline.due(self.world.time()) {
+            self.record_keyframe();
+        }
+
+        // Trails run off of simulation time, so they freeze when paused and speed up with the sim.
+        let dt = steps as f64 * self.clock.step_dt();
+        let sim_time = Duration::from_secs_f64(self.world.time());
+
+        let alpha = self.clock.alpha();
+        self.camera.update_follow(&self.world, alpha);
+
+        if let Some(id) = self.selected {
+            if !self.world.bodies().contains_key(&id) {
+                self.selected = None;
+            }
+        }
 
+        for (id, rc) in self.world.bodies().iter() {
             // if planet has trail
-            if let Some(p_trail) = self.planet_trails.get_mut(&me.id) {
-                p_trail.pos.x = me.pos.x as f32;
-                p_trail.pos.y = me.pos.y as f32;
+            if let Some(p_trail) = self.planet_trails.get_mut(id) {
+                let pl = rc.borrow();
+                p_trail.pos = cast_point2_to_f32!(pl.interpolated_pos(alpha));
             }
         }
 
         for (_, trail_sys) in self.planet_trails.iter_mut() {
-            trail_sys.update(dt, &time_since_start);
+            trail_sys.update(dt, &sim_time);
         }
 
-        // for pl in planets_to_add {
-        //     self.add_existing_planet(pl);
-        // }
+        if let Some(ref mut recorder) = self.replay_recorder {
+            recorder.end_frame(frame_dt, steps);
+        }
 
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        let time_since_start = timer::time_since_start(ctx);
+        let sim_time = Duration::from_secs_f64(self.world.time());
+        let alpha = self.clock.alpha();
         graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
 
+        // Draw the world through the camera
+        graphics::set_screen_coordinates(ctx, self.camera.world_rect())?;
+
         // Display particles behind planets
         for (_, sys) in self.planet_trails.iter() {
-            sys.draw(&time_since_start, &mut self.smoke_sprite_batch)?;
+            sys.draw(&sim_time, &mut self.smoke_sprite_batch)?;
         }
         graphics::draw(ctx, &self.smoke_sprite_batch, DrawParam::new())?;
         self.smoke_sprite_batch.clear();
 
-        for (_, rc) in self.planets.iter() {
+        for (_, rc) in self.world.bodies().iter() {
             //println!("Drawing: {}", k);
-            rc.borrow().draw(ctx)?;
+            rc.borrow().draw(ctx, alpha)?;
+        }
+
+        if let Some(rc) = self.selected.and_then(|id| self.world.bodies().get(&id)) {
+            let px = (1.0 / self.camera.zoom) as f32;   // One screen pixel in world units
+            rc.borrow().draw_outline(ctx, alpha, 4.0 * px, 2.0 * px)?;
         }
 
-        if self.mouse_info.down
+        let dragging = self.mouse_info.down
             && self.mouse_info.button_down == MouseButton::Left
             && tools::distance_squared_to(
                 &self.mouse_info.down_pos,
                 &self.mouse_info.current_drag_position,
-            ) >= 4.0
-        {
+            ) >= 4.0;
+
+        if dragging {
+            let origin = self.camera.screen_to_world(&self.mouse_info.down_pos);
+            self.draw_fake_planet(ctx, cast_point2_to_f32!(origin), 5.0)?;
+        }
+
+        // Back to screen coordinates for anything on top
+        graphics::set_screen_coordinates(ctx, self.camera.screen_rect())?;
+
+        if dragging {
             self.mouse_info.draw_mouse_drag(ctx)?;
-            self.draw_fake_planet(ctx, self.mouse_info.down_pos, 5.0)?;
         }
 
         self.draw_fps_and_info(ctx)?;
+        if let Some(id) = self.selected {
+            self.draw_selection_info(ctx, id)?;
+        }
+        self.timeline.draw(ctx, self.camera.screen_rect(), self.world.time())?;
+        if self.save_browser.open {
+            self.save_browser.draw(ctx, self.camera.screen_rect())?;
+        }
+        if self.autosaver.recovered.is_some() {
+            self.draw_restore_prompt(ctx)?;
+        }
+        self.console.draw(ctx, self.camera.screen_rect())?;
 
         graphics::present(ctx)?;
         Ok(())
     }
 
     fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
-        self.mouse_info.down = true;
-        self.mouse_info.button_down = button;
-        self.mouse_info.down_pos = Point2::new(x, y);
+        self.user_input(Input::MouseDown { button, x, y });
     }
 
     fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
-        self.mouse_info.down = false;
-        let origin = Point2::new(
-            self.mouse_info.down_pos.x as f64,
-            self.mouse_info.down_pos.y as f64,
-        );
+        self.user_input(Input::MouseUp { button, x, y });
+    }
 
-        if button == MouseButton::Left {
-            self.add_planet(origin, origin - Point2::new(x as f64, y as f64), 5.0);
-        }
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, dx: f32, dy: f32) {
+        self.user_input(Input::MouseMotion { x, y, dx, dy });
     }
 
-    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
-        self.mouse_info.current_drag_position = Point2::new(x, y);
+    fn mouse_wheel_event(&mut self, _ctx: &mut Context, x: f32, y: f32) {
+        self.user_input(Input::Wheel { x, y });
+    }
+
+    // Only called on a clean exit, so the autosave lock file is left behind after a crash.
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        self.stop_recording();
+        self.stop_diagnostics_recording();
+        self.stop_replay_recording();
+        self.autosaver.finish();
+        false
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, ch: char) {
+        self.user_input(Input::Text(ch));
     }
 
     fn key_down_event(
         &mut self,
-        ctx: &mut Context,
+        _ctx: &mut Context,
         key: KeyCode,
         mods: KeyMods,
-        _repeat: bool,
+        repeat: bool,
     ) {
-        match key {
-            KeyCode::R => {
-                self.clear_planets();
-                if mods.contains(KeyMods::CTRL) {       // CTRL + R to clear planets AND save
-                    self.quick_save = None;
-                }
-            },
-            KeyCode::S => {
-                if mods.contains(KeyMods::CTRL) {
-                    self.save_to_file(ctx, Path::new("/save.bin")).unwrap();
-                } else {
-                    self.save_to_quick_save();
-                }
-            },
-            KeyCode::L => {
-                if mods.contains(KeyMods::CTRL) {
-                    self.load_from_file(ctx, Path::new("/save.bin")).unwrap();
-                } else {
-                    self.load_from_quick_save();
-                }
-            },
-            _ => ()
-        }
+        self.user_input(Input::KeyDown { key, mods: mods.bits(), repeat });
     }
-}
-
-// Important fields from MainState
-#[derive(Serialize, Deserialize, Default)]
-struct SaveState {
-    planets: HashMap<BodyID, BodySaveData>,
-}
 
-impl SaveState {
-    fn new_from_main_state(main: &MainState) -> SaveState {
-        SaveState {
-            planets: Self::planet_save_data_from_planets(&main.planets),
-        }
+    fn key_up_event(&mut self, _ctx: &mut Context, key: KeyCode, _mods: KeyMods) {
+        self.user_input(Input::KeyUp { key });
     }
+}
 
-    fn load_from_file(ctx: &mut Context, path: &Path) -> GameResult<SaveState> {
-        let mut file = filesystem::open(ctx, path)?;
-        let mut full_data = Vec::<u8>::new();
-        file.read_to_end(&mut full_data)?;
-
-        let mut save = SaveState::default();
-        save.planets = bincode::deserialize(full_data.as_slice()).unwrap();
+pub fn main() -> GameResult {
+    use ggez::conf::{NumSamples, WindowSetup, WindowMode, FullscreenType};
 
-        Ok(save)
+    let opts = match CliOptions::parse(std::env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        },
+    };
+    if opts.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
     }
-
-    fn planet_save_data_from_planets(map: &HashMap<BodyID, RefCell<Body>>) -> HashMap<BodyID, BodySaveData> {
-        let mut out_map: HashMap<BodyID, BodySaveData> = HashMap::new();
-        for (key, val) in map.iter() {
-            out_map.insert(*key, val.borrow().into());
+    if opts.headless {
+        if let Err(e) = headless::run(&opts) {
+            eprintln!("{}", e);
+            std::process::exit(1);
         }
-        out_map
+        return Ok(());
     }
-}
-
-
-pub fn main() -> GameResult {
-    use ggez::conf::{NumSamples, WindowSetup, WindowMode, FullscreenType};
 
     let mut cb = ggez::ContextBuilder::new("Orbits", "eggmund")
         .window_setup(WindowSetup {
@@ -504,6 +1465,6 @@ pub fn main() -> GameResult {
     }
 
     let (ctx, event_loop) = &mut cb.build()?;
-    let state = &mut MainState::new(ctx)?;
+    let state = &mut MainState::new(ctx, &opts)?;
     event::run(ctx, event_loop, state)
 }
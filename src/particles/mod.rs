@@ -10,6 +10,7 @@ pub trait ParticleSystem {
     fn particle_count(&self) -> usize;
 }
 
+#[allow(dead_code)]
 pub trait Particle: Mobile<f32> {
     fn time_created(&self) -> &Duration;
     fn lifetime(&self) -> &Duration;
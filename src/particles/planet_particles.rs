@@ -2,7 +2,8 @@ use ggez::graphics::{DrawParam, spritebatch};
 use ggez::nalgebra as na;
 use ggez::{GameResult, timer};
 use na::{Point2, Vector2};
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
 
 use std::collections::VecDeque;
 use std::time::Duration;
@@ -19,18 +20,20 @@ const SMOKE_IMAGE_DIMENSIONS: [usize; 2] = [512, 512];
 
 pub struct PlanetTrailParticleSys {
     particles: VecDeque<PlanetTrailParticle>,
-    rand_thread: ThreadRng,
+    seed: u64,      // From `World::stream_seed`, so trails look the same every run
+    emitted: u64,   // Each particle gets its own rng from the seed and this, so saves only need the two
     emmision_timer: f64,
 }
 
 impl PlanetTrailParticleSys {
-    pub fn new() -> PlanetTrailParticleSys {
+    pub fn new(seed: u64) -> PlanetTrailParticleSys {
         // Expected max particles = particle_lifetime/particle_emmision_period + 1
         const EXPECTED_MAX_PARTICLE_NUM: usize = 76;
 
         let mut p = PlanetTrailParticleSys {
             particles: VecDeque::with_capacity(EXPECTED_MAX_PARTICLE_NUM),
-            rand_thread: rand::thread_rng(),
+            seed,
+            emitted: 0,
             emmision_timer: 0.0,
         };
 
@@ -39,19 +42,49 @@ impl PlanetTrailParticleSys {
         p
     }
 
+    pub fn from_save_data(emmision_timer: f64, seed: u64, emitted: u64, particles: &[TrailParticleSaveData]) -> PlanetTrailParticleSys {
+        PlanetTrailParticleSys {
+            particles: particles.iter().map(PlanetTrailParticle::from).collect(),
+            seed,
+            emitted,
+            emmision_timer,
+        }
+    }
+
+    #[inline]
+    pub fn emmision_timer(&self) -> f64 {
+        self.emmision_timer
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[inline]
+    pub fn emitted(&self) -> u64 {
+        self.emitted
+    }
+
+    pub fn particle_save_data(&self) -> Vec<TrailParticleSaveData> {
+        self.particles.iter().map(TrailParticleSaveData::from).collect()
+    }
+
     fn add_particle(&mut self, current_time: &Duration, pos: &Point2<f32>) {
+        let mut rand_thread = StdRng::seed_from_u64(self.seed.wrapping_add(self.emitted));
+        self.emitted += 1;
+
         self.particles.push_back(PlanetTrailParticle::new(
             *pos,
-            Self::get_new_particle_vel(&mut self.rand_thread),
-            self.rand_thread
-                .gen_range(PARTICLE_RAD_LIMITS.0, PARTICLE_RAD_LIMITS.1),
-            self.rand_thread.gen::<f32>() * TWO_PI as f32,
-            current_time.clone(),
+            Self::get_new_particle_vel(&mut rand_thread),
+            rand_thread.gen_range(PARTICLE_RAD_LIMITS.0, PARTICLE_RAD_LIMITS.1),
+            rand_thread.gen::<f32>() * TWO_PI as f32,
+            *current_time,
         ));
     }
 
     #[inline]
-    fn get_new_particle_vel(rand_thread: &mut ThreadRng) -> Vector2<f32> {
+    fn get_new_particle_vel(rand_thread: &mut StdRng) -> Vector2<f32> {
         tools::get_components(
             rand_thread.gen_range(-PARTICLE_SPEED_LIMIT, PARTICLE_SPEED_LIMIT),
             rand_thread.gen_range(0.0, TWO_PI as f32)
@@ -93,7 +126,7 @@ impl PlanetTrailParticleSys {
                         .offset([0.5, 0.5])
                         .scale([SCALE[0] * p.rad, SCALE[1] * p.rad])
                         .rotation(p.rotation)
-                        .color([0.15671875, 0.88328125, 0.72359375, alpha as f32].into());
+                        .color([0.15671875, 0.88328125, 0.723_593_8, alpha as f32].into());
                 
                 batch.add(params);
             }
@@ -154,3 +187,40 @@ impl Mobile<f32> for PlanetTrailParticle {
 impl Particle for PlanetTrailParticle {
     particle_set_get_defaults!(&PARTICLE_LIFETIME);
 }
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TrailParticleSaveData {
+    pos_x: f32,
+    pos_y: f32,
+    vel_x: f32,
+    vel_y: f32,
+    rad: f32,
+    rotation: f32,
+    time_created: Duration,
+}
+
+impl From<&PlanetTrailParticle> for TrailParticleSaveData {
+    fn from(p: &PlanetTrailParticle) -> TrailParticleSaveData {
+        TrailParticleSaveData {
+            pos_x: p.pos.x,
+            pos_y: p.pos.y,
+            vel_x: p.vel.x,
+            vel_y: p.vel.y,
+            rad: p.rad,
+            rotation: p.rotation,
+            time_created: p.time_created,
+        }
+    }
+}
+
+impl From<&TrailParticleSaveData> for PlanetTrailParticle {
+    fn from(p: &TrailParticleSaveData) -> PlanetTrailParticle {
+        PlanetTrailParticle::new(
+            Point2::new(p.pos_x, p.pos_y),
+            Vector2::new(p.vel_x, p.vel_y),
+            p.rad,
+            p.rotation,
+            p.time_created,
+        )
+    }
+}
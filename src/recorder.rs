@@ -0,0 +1,334 @@
+// Recording bodies over time for looking at elsewhere. `TrajectoryRecorder` streams every body to a
+// CSV file once per interval of sim time, with merges/removals getting a row each as they happen.
+// `NpzRecorder` writes a NumPy snapshot per interval instead, for runs too big for CSV.
+// `DiagnosticsRecorder` writes the conserved quantities (see `diagnostics`) to a CSV file.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    body::{Body, BodyID, BodySaveData, Mobile},
+    diagnostics::Diagnostics,
+    save::npz,
+    world::{Merge, World},
+};
+
+pub const DEFAULT_INTERVAL: f64 = 0.1;     // Seconds of sim time
+
+// Sim time is a sum of many small steps, so it lands just short of where it should be.
+const TIME_TOLERANCE: f64 = 1e-9;
+
+// Keeps samples on a grid of intervals, skipping any that were stepped over. If time goes back (the
+// world was cleared, loaded etc) the grid starts again from there.
+struct Sampler {
+    interval: f64,
+    next: f64,
+    last: f64,  // Time of the last sample
+}
+
+impl Sampler {
+    fn new(interval: f64, start: f64) -> Sampler {
+        Sampler { interval, next: start, last: f64::NEG_INFINITY }
+    }
+
+    fn due(&mut self, time: f64) -> bool {
+        if time < self.last {
+            self.restart(time);
+        }
+        if time + TIME_TOLERANCE < self.next {
+            return false;
+        }
+        let missed = ((time - self.next) / self.interval).floor().max(0.0) + 1.0;
+        self.next += missed * self.interval;
+        self.last = time;
+        true
+    }
+
+    // Due straight away.
+    fn restart(&mut self, time: f64) {
+        self.next = time;
+        self.last = f64::NEG_INFINITY;
+    }
+}
+
+const HEADER: &str = "time,event,id,name,pos_x,pos_y,vel_x,vel_y,mass,other_id";
+
+/* Rows:
+    -- sample: a body's state at that time.
+    -- merge: `id` absorbed `other_id`, the state is `id`'s after the merge.
+    -- removed: the body was deleted or cleared, with its state when it went.
+    -- reset: the world jumped to a different state (loaded, undone etc), so rows before it don't lead
+       on to rows after it. Only has the time.
+*/
+pub struct TrajectoryRecorder {
+    out: BufWriter<File>,
+    path: PathBuf,
+    sampler: Sampler,
+    rows: usize,
+}
+
+impl TrajectoryRecorder {
+    pub fn start(path: &Path, interval: f64, world: &World) -> io::Result<TrajectoryRecorder> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{}", HEADER)?;
+
+        let mut recorder = TrajectoryRecorder {
+            out,
+            path: path.to_owned(),
+            sampler: Sampler::new(interval, world.time()),
+            rows: 0,
+        };
+        recorder.step(world, &[])?;    // Starting state
+
+        Ok(recorder)
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[inline]
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    // Call after every step.
+    pub fn step(&mut self, world: &World, merges: &[Merge]) -> io::Result<()> {
+        for m in merges.iter() {
+            if let Some(survivor) = world.bodies().get(&m.survivor) {
+                self.write_row(world.time(), "merge", &survivor.borrow(), Some(m.absorbed))?;
+            }
+        }
+
+        if self.sampler.due(world.time()) {
+            for body in world.bodies().values() {
+                self.write_row(world.time(), "sample", &body.borrow(), None)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn removed(&mut self, time: f64, body: &Body) -> io::Result<()> {
+        self.write_row(time, "removed", body, None)
+    }
+
+    // Call after the world jumps to a different state, to mark it and sample the new one.
+    pub fn restart(&mut self, world: &World) -> io::Result<()> {
+        writeln!(self.out, "{},reset,,,,,,,,", world.time())?;
+        self.rows += 1;
+        self.sampler.restart(world.time());
+        self.step(world, &[])
+    }
+
+    pub fn finish(mut self) -> io::Result<usize> {
+        self.out.flush()?;
+        Ok(self.rows)
+    }
+
+    fn write_row(&mut self, time: f64, event: &str, body: &Body, other: Option<BodyID>) -> io::Result<()> {
+        let vel = body.vel();
+        writeln!(
+            self.out,
+            "{},{},{},{},{},{},{},{},{},{}",
+            time,
+            event,
+            body.id,
+            escape(body.name.as_deref().unwrap_or("")),
+            body.pos.x,
+            body.pos.y,
+            vel.x,
+            vel.y,
+            body.mass,
+            other.map(|id| id.to_string()).unwrap_or_default(),
+        )?;
+        self.rows += 1;
+        Ok(())
+    }
+}
+
+// Quotes anything with commas, quotes or newlines in it.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+// Writes `snapshot-00000.npz`, `snapshot-00001.npz`... into a directory, see `npz` for what's in them.
+pub struct NpzRecorder {
+    dir: PathBuf,
+    sampler: Sampler,
+    snapshots: usize,
+}
+
+impl NpzRecorder {
+    pub fn start(dir: &Path, interval: f64, world: &World) -> io::Result<NpzRecorder> {
+        fs::create_dir_all(dir)?;
+
+        let mut recorder = NpzRecorder {
+            dir: dir.to_owned(),
+            sampler: Sampler::new(interval, world.time()),
+            snapshots: 0,
+        };
+        recorder.step(world)?;     // Starting state
+
+        Ok(recorder)
+    }
+
+    #[inline]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    #[inline]
+    pub fn snapshots(&self) -> usize {
+        self.snapshots
+    }
+
+    // Call after every step.
+    pub fn step(&mut self, world: &World) -> io::Result<()> {
+        if self.sampler.due(world.time()) {
+            let bodies: Vec<BodySaveData> = world.bodies().values().map(|b| b.borrow().into()).collect();
+            let path = self.dir.join(format!("snapshot-{:05}.npz", self.snapshots));
+            fs::write(path, npz::snapshot(world.time(), &bodies))?;
+            self.snapshots += 1;
+        }
+        Ok(())
+    }
+
+    // Call after the world jumps to a different state. Snapshots stand alone, so this just takes one.
+    pub fn restart(&mut self, world: &World) -> io::Result<()> {
+        self.sampler.restart(world.time());
+        self.step(world)
+    }
+}
+
+const DIAGNOSTICS_HEADER: &str = "time,kinetic,potential,total,collision_energy,energy_drift,\
+momentum_x,momentum_y,momentum_drift,angular_momentum,angular_momentum_drift";
+
+/* One row per interval. Drift is relative to the first row (see `Diagnostics::drift_since`), blank
+   if there's nothing to compare against, and `collision_energy` is what merges have taken out since
+   the first row. total + collision_energy should stay constant. After the world jumps to a different
+   state (loaded, undone etc) everything is relative to the first row after it instead.
+*/
+pub struct DiagnosticsRecorder {
+    out: BufWriter<File>,
+    path: PathBuf,
+    sampler: Sampler,
+    start: Diagnostics,
+    rows: usize,
+}
+
+impl DiagnosticsRecorder {
+    pub fn start(path: &Path, interval: f64, world: &World) -> io::Result<DiagnosticsRecorder> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{}", DIAGNOSTICS_HEADER)?;
+
+        let mut recorder = DiagnosticsRecorder {
+            out,
+            path: path.to_owned(),
+            sampler: Sampler::new(interval, world.time()),
+            start: Diagnostics::measure(world),
+            rows: 0,
+        };
+        recorder.step(world)?;     // Starting state
+
+        Ok(recorder)
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Call after every step.
+    pub fn step(&mut self, world: &World) -> io::Result<()> {
+        if !self.sampler.due(world.time()) {
+            return Ok(());
+        }
+
+        let d = Diagnostics::measure(world);
+        let drift = d.drift_since(&self.start);
+        let blank_if_none = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+        writeln!(
+            self.out,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            d.time,
+            d.kinetic,
+            d.potential,
+            d.total_energy(),
+            d.collision_energy - self.start.collision_energy,
+            blank_if_none(drift.energy),
+            d.momentum.x,
+            d.momentum.y,
+            blank_if_none(drift.momentum),
+            d.angular_momentum,
+            blank_if_none(drift.angular_momentum),
+        )?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    // Call after the world jumps to a different state.
+    pub fn restart(&mut self, world: &World) -> io::Result<()> {
+        self.start = Diagnostics::measure(world);
+        self.sampler.restart(world.time());
+        self.step(world)
+    }
+
+    pub fn finish(mut self) -> io::Result<usize> {
+        self.out.flush()?;
+        Ok(self.rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ggez::nalgebra::{Point2, Vector2};
+
+    #[test]
+    fn sampler_restarts_when_time_goes_back() {
+        let mut sampler = Sampler::new(0.1, 0.0);
+        let due: Vec<bool> = [0.0, 0.05, 0.1, 0.35, 0.0, 0.05, 0.1].iter().map(|&t| sampler.due(t)).collect();
+        assert_eq!(due, vec![true, false, true, true, true, false, true]);
+    }
+
+    #[test]
+    fn sampling_resumes_after_clearing() {
+        let path = std::env::temp_dir().join(format!("orbits-test-{}-trajectory.csv", std::process::id()));
+        let dt = 1.0 / 120.0;
+        let mut world = World::new();
+        world.add_body(Point2::new(0.0, 0.0), Vector2::new(10.0, 0.0), 5.0);
+        let mut recorder = TrajectoryRecorder::start(&path, 0.1, &world).unwrap();
+        for _ in 0..60 {
+            world.step(dt);
+            recorder.step(&world, &[]).unwrap();
+        }
+
+        // Back to time 0, which is well before the next sample was due
+        world.clear();
+        world.add_body(Point2::new(0.0, 0.0), Vector2::new(0.0, 10.0), 5.0);
+        for _ in 0..30 {
+            world.step(dt);
+            recorder.step(&world, &[]).unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let csv = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        let times: Vec<f64> = csv
+            .lines()
+            .skip(1)
+            .filter(|row| row.split(',').nth(1) == Some("sample"))
+            .map(|row| row.split(',').next().unwrap().parse().unwrap())
+            .collect();
+        let cleared_at = times.windows(2).position(|w| w[1] < w[0]).expect("no samples after clearing") + 1;
+        assert_eq!(cleared_at, 6);  // 0, 0.1 ... 0.5
+        assert_eq!(times.len() - cleared_at, 3);  // Straight away, then 0.1 and 0.2 on from it
+    }
+}
@@ -0,0 +1,198 @@
+// Recording every input along with how long each frame took, so that a run can be played back
+// exactly, e.g to reproduce a collision bug. Playback feeds the recorded frame times to `update`
+// instead of the real ones, and the recorded input to the same handlers real input goes to.
+
+use ggez::event::{KeyCode, MouseButton};
+use serde::{Serialize, Deserialize};
+
+use std::fs;
+use std::path::Path;
+
+use crate::{
+    body::BodySaveData,
+    save::{SaveError, SaveState},
+    world::World,
+};
+
+const MAGIC: &[u8; 8] = b"ORBITRPL";
+const FORMAT_VERSION: u16 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Input {
+    KeyDown { key: KeyCode, mods: u8, repeat: bool },  // mods are `KeyMods` bits
+    KeyUp { key: KeyCode },
+    Text(char),
+    MouseDown { button: MouseButton, x: f32, y: f32 },
+    MouseUp { button: MouseButton, x: f32, y: f32 },
+    MouseMotion { x: f32, y: f32, dx: f32, dy: f32 },
+    Wheel { x: f32, y: f32 },
+    Loaded(Vec<u8>),    // What loading a save or scenario from disk left, encoded like a save file
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReplayEvent {
+    pub frame: u64,     // Handled just before this frame's update
+    pub step: u64,      // Physics steps done by then, for reading the log
+    pub input: Input,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    #[serde(with = "encoded_save")]
+    pub initial: SaveState,     // Includes the seed
+    pub frame_dts: Vec<f64>,
+    pub events: Vec<ReplayEvent>,
+    pub checksum: u64,  // Of the world at the end, to check the playback came out the same
+}
+
+impl Replay {
+    pub fn save_to_file(&self, path: &Path) -> Result<(), SaveError> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.extend(bincode::serialize(self)?);
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Replay, SaveError> {
+        let data = fs::read(path)?;
+        if !data.starts_with(MAGIC) {
+            return Err(SaveError::NotASave);
+        }
+        if data.len() < MAGIC.len() + 2 {
+            return Err(SaveError::Truncated);
+        }
+
+        let payload = &data[MAGIC.len() + 2..];
+        match u16::from_le_bytes([data[8], data[9]]) {
+            FORMAT_VERSION => Ok(bincode::deserialize(payload)?),
+            version if version > FORMAT_VERSION => Err(SaveError::NewerVersion { version, supported: FORMAT_VERSION }),
+            version => Err(SaveError::Corrupt(format!("there is no replay version {}", version))),
+        }
+    }
+}
+
+pub struct ReplayRecorder {
+    replay: Replay,
+    steps: u64,
+}
+
+impl ReplayRecorder {
+    pub fn new(initial: SaveState) -> ReplayRecorder {
+        ReplayRecorder {
+            replay: Replay {
+                initial,
+                frame_dts: Vec::new(),
+                events: Vec::new(),
+                checksum: 0,
+            },
+            steps: 0,
+        }
+    }
+
+    pub fn input(&mut self, input: Input) {
+        self.replay.events.push(ReplayEvent {
+            frame: self.replay.frame_dts.len() as u64,
+            step: self.steps,
+            input,
+        });
+    }
+
+    pub fn end_frame(&mut self, frame_dt: f64, steps: u32) {
+        self.replay.frame_dts.push(frame_dt);
+        self.steps += steps as u64;
+    }
+
+    #[inline]
+    pub fn frames(&self) -> usize {
+        self.replay.frame_dts.len()
+    }
+
+    pub fn finish(mut self, world: &World) -> Replay {
+        self.replay.checksum = checksum(world);
+        self.replay
+    }
+}
+
+pub struct ReplayPlayer {
+    replay: Replay,
+    frame: usize,
+    next_event: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> ReplayPlayer {
+        ReplayPlayer {
+            replay,
+            frame: 0,
+            next_event: 0,
+        }
+    }
+
+    #[inline]
+    pub fn initial(&self) -> &SaveState {
+        &self.replay.initial
+    }
+
+    #[inline]
+    pub fn progress(&self) -> (usize, usize) {
+        (self.frame, self.replay.frame_dts.len())
+    }
+
+    #[inline]
+    pub fn finished(&self) -> bool {
+        self.frame >= self.replay.frame_dts.len()
+    }
+
+    // Input to handle before the current frame. After the last frame this is whatever came after it.
+    pub fn take_inputs(&mut self) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        while let Some(event) = self.replay.events.get(self.next_event) {
+            if event.frame as usize > self.frame && !self.finished() {
+                break;
+            }
+            inputs.push(event.input.clone());
+            self.next_event += 1;
+        }
+        inputs
+    }
+
+    // Frame time for the current frame, then moves on to the next.
+    pub fn next_frame_dt(&mut self) -> Option<f64> {
+        let dt = *self.replay.frame_dts.get(self.frame)?;
+        self.frame += 1;
+        Some(dt)
+    }
+
+    pub fn matches(&self, world: &World) -> bool {
+        checksum(world) == self.replay.checksum
+    }
+}
+
+// FNV-1a over the encoded bodies (in order of id) and the time.
+pub fn checksum(world: &World) -> u64 {
+    let bodies: Vec<BodySaveData> = world.bodies().values().map(|b| b.borrow().into()).collect();
+
+    let mut data = bincode::serialize(&bodies).unwrap_or_default();
+    data.extend_from_slice(&world.time().to_le_bytes());
+
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+// The starting state is kept encoded like a save file, so older replays still load after
+// `SaveState` changes (see `save::migrate`).
+mod encoded_save {
+    use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::save::SaveState;
+
+    pub fn serialize<S: Serializer>(state: &SaveState, serializer: S) -> Result<S::Ok, S::Error> {
+        state.encode().map_err(ser::Error::custom)?.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SaveState, D::Error> {
+        let data = Vec::<u8>::deserialize(deserializer)?;
+        SaveState::decode(&data).map(|(_, state)| state).map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,129 @@
+// Periodic autosaves, written on a background thread into a few rotating files. A lock file is kept
+// for as long as the game is running, so if it is still there on startup the last session crashed
+// (or was killed) and the newest autosave is worth offering back.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+use super::{SaveError, SaveState};
+
+pub const AUTOSAVE_PERIOD: f64 = 60.0;  // Seconds of real time
+const AUTOSAVE_DIR: &str = "autosave";
+const AUTOSAVE_COUNT: usize = 3;
+const LOCK_FILE: &str = "session.lock";
+
+pub struct Autosaver {
+    dir: PathBuf,
+    sender: Option<Sender<SaveState>>,
+    thread: Option<JoinHandle<()>>,
+    timer: f64,
+    pub recovered: Option<PathBuf>,    // Newest autosave, if the last session didn't exit cleanly
+}
+
+impl Autosaver {
+    // Takes the lock for this session.
+    pub fn start(user_data_dir: &Path) -> Autosaver {
+        let dir = user_data_dir.join(AUTOSAVE_DIR);
+        let lock = dir.join(LOCK_FILE);
+
+        let recovered = if lock.is_file() { newest_autosave(&dir) } else { None };
+
+        if let Err(e) = fs::create_dir_all(&dir).and_then(|()| fs::write(&lock, b"")) {
+            eprintln!("Couldn't create autosave lock file {}: {}", lock.display(), e);
+        }
+
+        let (sender, receiver) = mpsc::channel::<SaveState>();
+        let thread_dir = dir.clone();
+        let thread = thread::spawn(move || {
+            let mut next = newest_autosave(&thread_dir)
+                .and_then(|p| autosave_index(&p))
+                .map_or(0, |i| (i + 1) % AUTOSAVE_COUNT);
+
+            for state in receiver {
+                let path = autosave_path(&thread_dir, next);
+                match state.save_to_file(&path) {
+                    Ok(()) => next = (next + 1) % AUTOSAVE_COUNT,
+                    Err(e) => eprintln!("Autosave to {} failed: {}", path.display(), e),
+                }
+            }
+        });
+
+        Autosaver {
+            dir,
+            sender: Some(sender),
+            thread: Some(thread),
+            timer: 0.0,
+            recovered,
+        }
+    }
+
+    // True once every AUTOSAVE_PERIOD. Nothing is due while the restore offer is still up, so the
+    // autosave being offered can't be rotated away.
+    pub fn due(&mut self, dt: f64) -> bool {
+        if self.recovered.is_some() {
+            return false;
+        }
+
+        self.timer += dt;
+        if self.timer >= AUTOSAVE_PERIOD {
+            self.timer = 0.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Hands the save to the background thread to be encoded and written.
+    pub fn save(&self, state: SaveState) {
+        if let Some(ref sender) = self.sender {
+            // Only fails if the thread died, in which case there's nothing to be done about it.
+            let _ = sender.send(state);
+        }
+    }
+
+    pub fn load_recovered(&mut self) -> Result<SaveState, SaveError> {
+        match self.recovered.take() {
+            Some(path) => SaveState::load_from_file(&path),
+            None => Err(SaveError::NotASave),
+        }
+    }
+
+    // Waits for any autosave still being written, then gives up the lock. Call on a clean exit.
+    pub fn finish(&mut self) {
+        self.sender = None;     // Closes the channel so the thread ends
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        let lock = self.dir.join(LOCK_FILE);
+        if let Err(e) = fs::remove_file(&lock) {
+            eprintln!("Couldn't remove autosave lock file {}: {}", lock.display(), e);
+        }
+    }
+}
+
+fn autosave_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("autosave-{}.bin", index))
+}
+
+fn autosave_index(path: &Path) -> Option<usize> {
+    path.file_stem()?
+        .to_str()?
+        .strip_prefix("autosave-")?
+        .parse()
+        .ok()
+        .filter(|&i| i < AUTOSAVE_COUNT)
+}
+
+fn newest_autosave(dir: &Path) -> Option<PathBuf> {
+    (0..AUTOSAVE_COUNT)
+        .map(|i| autosave_path(dir, i))
+        .filter_map(|p| {
+            let modified = fs::metadata(&p).and_then(|m| m.modified()).ok()?;
+            Some((p, modified))
+        })
+        .max_by_key(|&(_, modified)| modified)
+        .map(|(p, _)| p)
+}
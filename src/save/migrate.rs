@@ -0,0 +1,172 @@
+// Loading older save formats. Each version gets its own copy of the types as they were when it was
+// written, and an upgrade to the version after it. When changing `SaveState` or anything in it, bump
+// `FORMAT_VERSION`, copy the old types in here and add an arm to `load_version`.
+
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+
+use super::{SaveError, SaveState, FORMAT_VERSION};
+use crate::{
+    body::{BodyID, BodySaveData, DEFAULT_COLOUR},
+    world::WorldSaveData,
+};
+
+pub fn load_version(version: u16, payload: &[u8]) -> Result<SaveState, SaveError> {
+    match version {
+        0 => {
+            // No header, so it could be anything. Failing to decode means it isn't a save.
+            let v0: V0SaveState = bincode::deserialize(payload).map_err(|e| match SaveError::from(e) {
+                SaveError::Corrupt(_) | SaveError::Truncated => SaveError::NotASave,
+                e => e,
+            })?;
+            Ok(v0.upgrade())
+        },
+        FORMAT_VERSION => Ok(bincode::deserialize(payload)?),
+        version => Err(SaveError::NewerVersion { version, supported: FORMAT_VERSION }),
+    }
+}
+
+// VERSION 0 //
+// Bincode blob of just the bodies, without a header.
+
+#[derive(Deserialize)]
+struct V0SaveState {
+    planets: BTreeMap<BodyID, V0BodySaveData>,
+}
+
+#[derive(Deserialize)]
+struct V0BodySaveData {
+    id: BodyID,
+    pos_x: f64,
+    pos_y: f64,
+    vel_x: f64,
+    vel_y: f64,
+    radius: f64,
+    mass: f64,
+}
+
+impl V0SaveState {
+    // Everything else starts off as new, with the id counter past the highest id.
+    fn upgrade(self) -> SaveState {
+        let id_counter = self.planets.keys().max().map_or(0, |id| id.wrapping_add(1));
+
+        SaveState {
+            planets: self.planets
+                .into_iter()
+                .map(|(id, pl)| (id, BodySaveData {
+                    id: pl.id,
+                    name: None,
+                    pos_x: pl.pos_x,
+                    pos_y: pl.pos_y,
+                    vel_x: pl.vel_x,
+                    vel_y: pl.vel_y,
+                    radius: pl.radius,
+                    mass: pl.mass,
+                    colour: DEFAULT_COLOUR,
+                    softening: None,
+                }))
+                .collect(),
+            world: WorldSaveData {
+                id_counter,
+                ..WorldSaveData::default()
+            },
+            clock: None,
+            camera: None,
+            trails: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save::{MAGIC, SaveState};
+
+    // The old format written out by hand. Bincode doesn't store field names, so tuples in the same
+    // order come out the same as the structs did.
+    type V0Body = (BodyID, f64, f64, f64, f64, f64, f64);
+
+    // Goes through a real file, like loading a slot does.
+    fn load(name: &str, data: &[u8]) -> Result<SaveState, SaveError> {
+        let path = std::env::temp_dir().join(format!("orbits-test-{}-{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        let result = SaveState::load_from_file(&path);
+        let _ = std::fs::remove_file(&path);
+        result
+    }
+
+    fn with_header(version: u16, payload: &[u8]) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&version.to_le_bytes());
+        data.extend_from_slice(&1_600_000_000u64.to_le_bytes());
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn loads_version_0() {
+        let mut planets: BTreeMap<BodyID, V0Body> = BTreeMap::new();
+        planets.insert(3, (3, 10.0, 20.0, 1.0, -1.0, 5.0, 500.0));
+        planets.insert(7, (7, -10.0, 0.0, 0.0, 2.0, 2.0, 40.0));
+        let save = load("v0", &bincode::serialize(&planets).unwrap()).unwrap();
+        assert_eq!(save.planets.len(), 2);
+        let pl = &save.planets[&7];
+        assert_eq!((pl.pos_x, pl.vel_y, pl.radius, pl.mass), (-10.0, 2.0, 2.0, 40.0));
+        assert_eq!(pl.name, None);
+        assert_eq!(pl.colour, DEFAULT_COLOUR);
+        assert_eq!(save.world.id_counter, 8);   // One past the highest id
+        assert_eq!(save.world.time, 0.0);
+        assert!(save.clock.is_none() && save.camera.is_none());
+    }
+
+    #[test]
+    fn current_version_round_trips() {
+        let mut save = SaveState::default();
+        save.world.time = 3.0;
+        save.world.seed = 99;
+        save.world.collision_energy = -250.0;
+        let loaded = load("current", &save.encode().unwrap()).unwrap();
+        assert_eq!((loaded.world.time, loaded.world.seed, loaded.world.collision_energy), (3.0, 99, -250.0));
+    }
+
+    #[test]
+    fn truncated_files() {
+        let mut save = SaveState::default();
+        save.planets.insert(0, BodySaveData {
+            id: 0,
+            name: None,
+            pos_x: 1.0,
+            pos_y: 2.0,
+            vel_x: 3.0,
+            vel_y: 4.0,
+            radius: 5.0,
+            mass: 6.0,
+            colour: DEFAULT_COLOUR,
+            softening: None,
+        });
+        let data = save.encode().unwrap();
+
+        // Cut off in the header, and in the body
+        for len in [MAGIC.len() + 1, data.len() - 3] {
+            assert!(matches!(load(&format!("truncated-{}", len), &data[..len]), Err(SaveError::Truncated)));
+        }
+    }
+
+    #[test]
+    fn foreign_files() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x10\0\0\0\x10\x08\x06\0\0\0";
+        let text = b"Just some notes, nothing to do with orbits.\n";
+        for (name, data) in [("png", &png[..]), ("text", &text[..]), ("empty", &[][..])] {
+            assert!(matches!(load(name, data), Err(SaveError::NotASave)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn newer_version() {
+        assert!(matches!(
+            load("newer", &with_header(FORMAT_VERSION + 1, &[])),
+            Err(SaveError::NewerVersion { version, .. }) if version == FORMAT_VERSION + 1
+        ));
+    }
+}
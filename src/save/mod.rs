@@ -0,0 +1,310 @@
+pub mod migrate;
+pub mod autosave;
+pub mod npz;
+pub mod scenario;
+pub mod slots;
+
+use serde::{Serialize, Deserialize};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::{
+    body::{Body, BodySaveData, BodyID, planet::{PlanetTrail, PlanetTrailSaveData}},
+    camera::{Camera, CameraSaveData},
+    clock::{SimClock, ClockSaveData},
+    gravity::GravitySolver,
+    timestep::Timestep,
+    tools,
+    world::{SimSettings, World, WorldSaveData},
+};
+
+/* Save file layout:
+    -- 8 bytes: MAGIC
+    -- u16 (little endian): format version
+    -- u64 (little endian): creation time, seconds since the unix epoch
+    -- bincode encoded `SaveState` (or an older version of it, see `migrate`)
+
+    Files from before the header existed have no magic, and are loaded as version 0.
+*/
+pub const MAGIC: &[u8; 8] = b"ORBITSAV";
+pub const FORMAT_VERSION: u16 = 1;
+const HEADER_LEN: usize = 8 + 2 + 8;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Truncated,
+    NotASave,
+    NewerVersion { version: u16, supported: u16 },  // Made by a newer version of Orbits
+    Corrupt(String),
+    UnknownFormat(String),  // File extension
+    Scenario(String),   // Problem with a scenario file, with where it is if serde knows
+    BadSlotName(String),
+    NoSuchSlot(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveError::Io(ref e) => write!(f, "IO error: {}", e),
+            SaveError::Truncated => write!(f, "Save file is truncated."),
+            SaveError::NotASave => write!(f, "Not an Orbits save file."),
+            SaveError::NewerVersion { version, supported } => write!(
+                f, "File is format version {}, but this version of Orbits only supports up to {}.", version, supported
+            ),
+            SaveError::Corrupt(ref e) => write!(f, "Save file is corrupt: {}", e),
+            SaveError::Scenario(ref e) => write!(f, "Bad scenario: {}", e),
+            SaveError::UnknownFormat(ref ext) => write!(f, "Unknown file type \".{}\" (expected .json or .ron).", ext),
+            SaveError::BadSlotName(ref name) => write!(
+                f, "\"{}\" can't be used as a save name, stick to letters, numbers, spaces, - and _.", name
+            ),
+            SaveError::NoSuchSlot(ref name) => write!(f, "There's no save called \"{}\".", name),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> SaveError {
+        SaveError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for SaveError {
+    fn from(e: bincode::Error) -> SaveError {
+        match *e {
+            bincode::ErrorKind::Io(ref io) if io.kind() == std::io::ErrorKind::UnexpectedEof => SaveError::Truncated,
+            ref e => SaveError::Corrupt(e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SaveHeader {
+    pub version: u16,
+    pub created: u64,   // Seconds since the unix epoch. 0 for files from before the header existed.
+}
+
+impl SaveHeader {
+    fn new() -> SaveHeader {
+        SaveHeader {
+            version: FORMAT_VERSION,
+            created: tools::unix_time(),
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&self.version.to_le_bytes());
+        out.extend_from_slice(&self.created.to_le_bytes());
+    }
+
+    // Returns the header and the rest of the data. None if there is no header.
+    fn read(data: &[u8]) -> Result<Option<(SaveHeader, &[u8])>, SaveError> {
+        if !data.starts_with(MAGIC) {
+            return Ok(None);
+        }
+        if data.len() < HEADER_LEN {
+            return Err(SaveError::Truncated);
+        }
+
+        let mut version = [0u8; 2];
+        let mut created = [0u8; 8];
+        version.copy_from_slice(&data[8..10]);
+        created.copy_from_slice(&data[10..HEADER_LEN]);
+
+        let header = SaveHeader {
+            version: u16::from_le_bytes(version),
+            created: u64::from_le_bytes(created),
+        };
+        Ok(Some((header, &data[HEADER_LEN..])))
+    }
+}
+
+// Everything needed to carry on exactly where the save was made. The clock and camera are optional
+// since saves from before the header don't have them, in which case whatever is current is kept.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct SaveState {
+    pub planets: BTreeMap<BodyID, BodySaveData>,
+    pub world: WorldSaveData,
+    pub clock: Option<ClockSaveData>,
+    pub camera: Option<CameraSaveData>,
+    pub trails: BTreeMap<BodyID, PlanetTrailSaveData>,
+}
+
+impl SaveState {
+    pub fn new(world: &World, clock: &SimClock, camera: &Camera, trails: &BTreeMap<BodyID, PlanetTrail>) -> SaveState {
+        SaveState {
+            planets: Self::planet_save_data_from_planets(world.bodies()),
+            world: world.save_data(),
+            clock: Some(clock.save_data()),
+            camera: Some(camera.save_data()),
+            trails: trails.iter().map(|(id, trail)| (*id, trail.save_data())).collect(),
+        }
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, SaveError> {
+        let mut out = Vec::with_capacity(HEADER_LEN + 64 * self.planets.len());
+        SaveHeader::new().write(&mut out);
+        out.extend(bincode::serialize(self)?);
+        Ok(out)
+    }
+
+    pub fn decode(data: &[u8]) -> Result<(SaveHeader, SaveState), SaveError> {
+        let (header, payload) = match SaveHeader::read(data)? {
+            Some(h) => h,
+            None => (SaveHeader { version: 0, created: 0 }, data),
+        };
+
+        let save = migrate::load_version(header.version, payload)?;
+        save.validate()?;
+
+        Ok((header, save))
+    }
+
+    // Written next to the target first and then renamed over it, so a crash half way through
+    // writing can't destroy the old save.
+    pub fn save_to_file(&self, path: &Path) -> Result<(), SaveError> {
+        let encoded = self.encode()?;
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, encoded)?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<SaveState, SaveError> {
+        let full_data = fs::read(path)?;
+        Self::decode(&full_data).map(|(_, save)| save)
+    }
+
+    // Catches garbage that bincode happily decoded, e.g from a foreign file with no header.
+    fn validate(&self) -> Result<(), SaveError> {
+        if !self.world.time.is_finite() || check_settings(&self.world.settings).is_err() {
+            return Err(SaveError::NotASave);
+        }
+        for pl in self.planets.values() {
+            if check_body(pl).is_err() || pl.mass <= 0.0 {
+                return Err(SaveError::NotASave);
+            }
+        }
+        Ok(())
+    }
+
+    fn planet_save_data_from_planets(map: &BTreeMap<BodyID, RefCell<Body>>) -> BTreeMap<BodyID, BodySaveData> {
+        map.iter().map(|(key, val)| (*key, val.borrow().into())).collect()
+    }
+}
+
+/* Checks shared by saves and scenarios, saying what's wrong. Anything that gets past these can be
+   simulated without turning into NaNs. */
+
+pub fn check_settings(settings: &SimSettings) -> Result<(), String> {
+    if let GravitySolver::BarnesHut { theta } = settings.gravity_solver {
+        if !(theta.is_finite() && theta > 0.0) {
+            return Err(format!("theta must be more than 0, not {}", theta));
+        }
+    }
+    if let Timestep::Adaptive { tolerance } = settings.timestep {
+        if !(tolerance.is_finite() && tolerance > 0.0) {
+            return Err(format!("tolerance must be more than 0, not {}", tolerance));
+        }
+    }
+    if !valid_softening(settings.softening) {
+        return Err(format!("softening can't be negative, not {}", settings.softening));
+    }
+    Ok(())
+}
+
+// A mass of 0 is allowed since scenarios leave it out to have it worked out from the radius.
+pub fn check_body(pl: &BodySaveData) -> Result<(), String> {
+    let numbers = [pl.pos_x, pl.pos_y, pl.vel_x, pl.vel_y, pl.radius, pl.mass];
+    if numbers.iter().any(|n| !n.is_finite()) {
+        return Err("position, velocity, radius and mass must all be finite".to_owned());
+    }
+    if pl.radius <= 0.0 {
+        return Err(format!("radius must be more than 0, not {}", pl.radius));
+    }
+    if pl.mass < 0.0 {
+        return Err(format!("mass can't be negative, not {}", pl.mass));
+    }
+    match pl.softening {
+        Some(length) if !valid_softening(length) => Err(format!("softening can't be negative, not {}", length)),
+        _ => Ok(()),
+    }
+}
+
+#[inline]
+fn valid_softening(length: f64) -> bool {
+    length.is_finite() && length >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ggez::nalgebra::{Point2, Vector2};
+    use std::time::Duration;
+
+    const DT: f64 = 1.0 / 120.0;
+
+    // What the game does each frame, without the drawing
+    fn step(world: &mut World, trails: &mut BTreeMap<BodyID, PlanetTrail>, steps: usize) {
+        for _ in 0..steps {
+            world.step(DT);
+            let now = Duration::from_secs_f64(world.time());
+            for (id, trail) in trails.iter_mut() {
+                if let Some(body) = world.bodies().get(id) {
+                    trail.pos = cast_point2_to_f32!(body.borrow().pos);
+                }
+                trail.update(DT, &now);
+            }
+        }
+    }
+
+    // Same as `MainState::load_from_save_state`
+    fn load(save: &SaveState) -> (World, BTreeMap<BodyID, PlanetTrail>) {
+        let mut world = World::new();
+        world.restore(&save.world);
+        for planet in save.planets.values() {
+            world.insert_body(planet.into());
+        }
+        let trails = save.trails.iter().map(|(id, t)| (*id, PlanetTrail::from_save_data(t))).collect();
+        (world, trails)
+    }
+
+    fn state(world: &World, trails: &BTreeMap<BodyID, PlanetTrail>) -> Vec<u8> {
+        let save = SaveState::new(world, &SimClock::new(120.0, 8), &Camera::new(800.0, 600.0), trails);
+        bincode::serialize(&save).unwrap()
+    }
+
+    #[test]
+    fn loaded_save_carries_on_identically() {
+        let mut world = World::new();
+        world.set_seed(42);
+        let sun = world.add_body(Point2::new(0.0, 0.0), Vector2::new(0.0, 0.0), 30.0);
+        let planet = world.add_body(Point2::new(300.0, 0.0), Vector2::new(0.0, 60.0), 5.0);
+        let mut trails: BTreeMap<BodyID, PlanetTrail> = [sun, planet]
+            .iter()
+            .map(|id| {
+                let pos = cast_point2_to_f32!(world.bodies()[id].borrow().pos);
+                (*id, PlanetTrail::new(pos, world.stream_seed(*id as u64)))
+            })
+            .collect();
+        step(&mut world, &mut trails, 100);
+
+        let encoded = SaveState::new(&world, &SimClock::new(120.0, 8), &Camera::new(800.0, 600.0), &trails)
+            .encode()
+            .unwrap();
+        let (_, decoded) = SaveState::decode(&encoded).unwrap();
+        let (mut loaded_world, mut loaded_trails) = load(&decoded);
+
+        step(&mut world, &mut trails, 200);
+        step(&mut loaded_world, &mut loaded_trails, 200);
+        assert!(trails[&planet].particle_count() > 1);
+        assert_eq!(state(&world, &trails), state(&loaded_world, &loaded_trails));
+    }
+}
@@ -0,0 +1,269 @@
+// NumPy snapshots of the bodies: one .npy array per quantity, bundled into an uncompressed .npz (a
+// plain zip). Loads with `numpy.load("snapshot.npz")`. Both formats are simple enough to write by hand.
+
+use std::fs;
+use std::path::Path;
+
+use super::SaveError;
+use crate::body::BodySaveData;
+
+/* Arrays, in order of id:
+    -- time: sim time of the snapshot, shape ()
+    -- ids: uint32, shape (n,)
+    -- positions: float64, shape (n, 2)
+    -- velocities: float64, shape (n, 2)
+    -- masses: float64, shape (n,)
+    -- radii: float64, shape (n,)
+*/
+pub fn export(path: &Path, time: f64, bodies: &[BodySaveData]) -> Result<(), SaveError> {
+    fs::write(path, snapshot(time, bodies))?;
+    Ok(())
+}
+
+pub fn snapshot(time: f64, bodies: &[BodySaveData]) -> Vec<u8> {
+    let mut bodies: Vec<&BodySaveData> = bodies.iter().collect();
+    bodies.sort_by_key(|b| b.id);
+    let n = bodies.len();
+
+    let ids: Vec<u8> = bodies.iter().flat_map(|b| b.id.to_le_bytes()).collect();
+    let positions = f64_bytes(bodies.iter().flat_map(|b| [b.pos_x, b.pos_y]));
+    let velocities = f64_bytes(bodies.iter().flat_map(|b| [b.vel_x, b.vel_y]));
+    let masses = f64_bytes(bodies.iter().map(|b| b.mass));
+    let radii = f64_bytes(bodies.iter().map(|b| b.radius));
+
+    let mut zip = ZipWriter::default();
+    zip.add("time.npy", &npy("<f8", &[], &time.to_le_bytes()));
+    zip.add("ids.npy", &npy("<u4", &[n], &ids));
+    zip.add("positions.npy", &npy("<f8", &[n, 2], &positions));
+    zip.add("velocities.npy", &npy("<f8", &[n, 2], &velocities));
+    zip.add("masses.npy", &npy("<f8", &[n], &masses));
+    zip.add("radii.npy", &npy("<f8", &[n], &radii));
+    zip.finish()
+}
+
+fn f64_bytes<I: Iterator<Item = f64>>(values: I) -> Vec<u8> {
+    values.flat_map(|v| v.to_le_bytes()).collect()
+}
+
+// NPY version 1.0: magic, version, header length, then a python dict literal padded with spaces so
+// the data starts on a 64 byte boundary.
+fn npy(descr: &str, shape: &[usize], data: &[u8]) -> Vec<u8> {
+    const PREAMBLE_LEN: usize = 6 + 2 + 2;
+
+    let shape = match shape.len() {
+        0 => "()".to_owned(),
+        1 => format!("({},)", shape[0]),
+        _ => format!("({})", shape.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+    let unpadded = PREAMBLE_LEN + header.len() + 1;    // + 1 for the newline
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut out = Vec::with_capacity(PREAMBLE_LEN + header.len() + data.len());
+    out.extend_from_slice(b"\x93NUMPY");
+    out.extend_from_slice(&[1, 0]);
+    out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+// Just enough zip for numpy: stored (uncompressed) files, no zip64, so each file and the whole
+// archive must stay under 4GB.
+#[derive(Default)]
+struct ZipWriter {
+    out: Vec<u8>,
+    central_directory: Vec<u8>,
+    entries: u16,
+}
+
+impl ZipWriter {
+    const VERSION: u16 = 20;
+    const DOS_DATE: u16 = (1 << 5) | 1;     // 1980-01-01, the earliest a zip can say
+
+    fn add(&mut self, name: &str, data: &[u8]) {
+        let offset = self.out.len() as u32;
+        let crc = crc32(data);
+
+        // Local file header
+        put_u32(&mut self.out, 0x0403_4b50);
+        Self::put_entry_fields(&mut self.out, crc, name, data.len() as u32);
+        self.out.extend_from_slice(name.as_bytes());
+        self.out.extend_from_slice(data);
+
+        // Central directory entry, written out at the end
+        let cd = &mut self.central_directory;
+        put_u32(cd, 0x0201_4b50);
+        put_u16(cd, Self::VERSION);    // Made by
+        Self::put_entry_fields(cd, crc, name, data.len() as u32);
+        put_u16(cd, 0);     // Comment length
+        put_u16(cd, 0);     // Disk number
+        put_u16(cd, 0);     // Internal attributes
+        put_u32(cd, 0);     // External attributes
+        put_u32(cd, offset);
+        cd.extend_from_slice(name.as_bytes());
+
+        self.entries += 1;
+    }
+
+    // The fields shared by local headers and central directory entries, up to the name/extra lengths.
+    fn put_entry_fields(out: &mut Vec<u8>, crc: u32, name: &str, size: u32) {
+        put_u16(out, Self::VERSION);   // Needed to extract
+        put_u16(out, 0);    // Flags
+        put_u16(out, 0);    // Stored
+        put_u16(out, 0);    // Time
+        put_u16(out, Self::DOS_DATE);
+        put_u32(out, crc);
+        put_u32(out, size);     // Compressed
+        put_u32(out, size);     // Uncompressed
+        put_u16(out, name.len() as u16);
+        put_u16(out, 0);    // Extra length
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let cd_offset = self.out.len() as u32;
+        let cd_len = self.central_directory.len() as u32;
+        self.out.extend_from_slice(&self.central_directory);
+
+        // End of central directory
+        put_u32(&mut self.out, 0x0605_4b50);
+        put_u16(&mut self.out, 0);  // This disk
+        put_u16(&mut self.out, 0);  // Disk with the central directory
+        put_u16(&mut self.out, self.entries);
+        put_u16(&mut self.out, self.entries);
+        put_u32(&mut self.out, cd_len);
+        put_u32(&mut self.out, cd_offset);
+        put_u16(&mut self.out, 0);  // Comment length
+
+        self.out
+    }
+}
+
+#[inline]
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+#[inline]
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+// CRC-32 as used by zip (reflected, polynomial 0xEDB88320).
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ggez::nalgebra::{Point2, Vector2};
+    use std::cell::RefCell;
+    use crate::body::Body;
+
+    fn u16_at(data: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([data[at], data[at + 1]])
+    }
+
+    fn u32_at(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn npy_headers() {
+        for &(shape, expected) in [(&[][..], "()"), (&[3][..], "(3,)"), (&[3, 2][..], "(3, 2)")].iter() {
+            let out = npy("<f8", shape, &[]);
+            assert_eq!(&out[..8], b"\x93NUMPY\x01\x00");
+
+            let header_len = u16_at(&out, 8) as usize;
+            assert_eq!((10 + header_len) % 64, 0);
+            assert_eq!(out.len(), 10 + header_len);
+
+            let header = std::str::from_utf8(&out[10..]).unwrap();
+            assert!(header.ends_with('\n'));
+            assert!(header.contains(&format!("'shape': {}, ", expected)), "{}", header);
+        }
+    }
+
+    // Walks the archive from the end of central directory record, checking that everything it
+    // points at is where it says.
+    fn check_zip(data: &[u8], expected_names: &[&str]) {
+        const EOCD_LEN: usize = 22;
+        let eocd = data.len() - EOCD_LEN;
+        assert_eq!(u32_at(data, eocd), 0x0605_4b50);
+
+        let entries = u16_at(data, eocd + 10) as usize;
+        assert_eq!(u16_at(data, eocd + 8) as usize, entries);
+        assert_eq!(entries, expected_names.len());
+        let cd_len = u32_at(data, eocd + 12) as usize;
+        let cd_offset = u32_at(data, eocd + 16) as usize;
+        assert_eq!(cd_offset + cd_len, eocd);
+
+        let mut at = cd_offset;
+        for name in expected_names.iter() {
+            assert_eq!(u32_at(data, at), 0x0201_4b50);
+            let crc = u32_at(data, at + 16);
+            let size = u32_at(data, at + 20) as usize;
+            let name_len = u16_at(data, at + 28) as usize;
+            let local = u32_at(data, at + 42) as usize;
+            assert_eq!(&data[at + 46..at + 46 + name_len], name.as_bytes());
+
+            assert_eq!(u32_at(data, local), 0x0403_4b50);
+            assert_eq!(u32_at(data, local + 14), crc);
+            assert_eq!(&data[local + 30..local + 30 + name_len], name.as_bytes());
+            let start = local + 30 + name_len;
+            assert_eq!(crc32(&data[start..start + size]), crc);
+
+            at += 46 + name_len;
+        }
+        assert_eq!(at, eocd);
+    }
+
+    const NAMES: [&str; 6] = ["time.npy", "ids.npy", "positions.npy", "velocities.npy", "masses.npy", "radii.npy"];
+
+    #[test]
+    fn empty_snapshot() {
+        check_zip(&snapshot(1.5, &[]), &NAMES);
+    }
+
+    #[test]
+    fn snapshot_with_bodies() {
+        let bodies: Vec<BodySaveData> = (0..5)
+            .rev()
+            .map(|i| {
+                let body = Body::new(i, Point2::new(i as f64, 0.0), Vector2::new(0.0, i as f64), 1.0 + i as f64, 0.0);
+                RefCell::new(body).borrow().into()
+            })
+            .collect();
+        let data = snapshot(2.0, &bodies);
+        check_zip(&data, &NAMES);
+        // 5 bodies' (x, y) after the header, in order of id
+        let positions = npy("<f8", &[5, 2], &f64_bytes((0..5).flat_map(|i| [i as f64, 0.0])));
+        assert!(data.windows(positions.len()).any(|w| w == &positions[..]));
+    }
+}
@@ -0,0 +1,173 @@
+// Human readable scenes (JSON or RON), for writing starting setups by hand and keeping them in git.
+// Unlike save files these go straight through std::fs, so paths are relative to where Orbits was run from.
+
+use serde::{Serialize, Deserialize};
+
+use std::path::Path;
+
+use super::{check_body, check_settings, SaveError};
+use crate::{
+    body::BodySaveData,
+    world::{SimSettings, World},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScenarioFormat {
+    Json,
+    Ron,
+}
+
+impl ScenarioFormat {
+    pub fn from_path(path: &Path) -> Result<ScenarioFormat, SaveError> {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        match ext.as_str() {
+            "json" => Ok(ScenarioFormat::Json),
+            "ron" => Ok(ScenarioFormat::Ron),
+            _ => Err(SaveError::UnknownFormat(ext)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Scenario {
+    #[serde(default)]
+    pub settings: SimSettings,
+    pub bodies: Vec<BodySaveData>,
+}
+
+impl Scenario {
+    pub fn from_world(world: &World) -> Scenario {
+        Scenario {
+            settings: world.settings,
+            bodies: world.bodies().values().map(|b| b.borrow().into()).collect(),  // In order of id, keeps diffs sensible
+        }
+    }
+
+    pub fn to_string(&self, format: ScenarioFormat) -> Result<String, SaveError> {
+        match format {
+            ScenarioFormat::Json => serde_json::to_string_pretty(self).map_err(|e| SaveError::Scenario(e.to_string())),
+            ScenarioFormat::Ron => ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|e| SaveError::Scenario(e.to_string())),
+        }
+    }
+
+    pub fn from_str(s: &str, format: ScenarioFormat) -> Result<Scenario, SaveError> {
+        let scenario: Scenario = match format {
+            ScenarioFormat::Json => serde_json::from_str(s).map_err(|e| SaveError::Scenario(e.to_string()))?,
+            ScenarioFormat::Ron => ron::from_str(s).map_err(|e| SaveError::Scenario(e.to_string()))?,
+        };
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    // Same checks as loading a save, so a typo can't fill the world with NaNs.
+    fn validate(&self) -> Result<(), SaveError> {
+        check_settings(&self.settings).map_err(|e| SaveError::Scenario(format!("settings: {}", e)))?;
+        for (i, body) in self.bodies.iter().enumerate() {
+            check_body(body).map_err(|e| {
+                let which = match body.name {
+                    Some(ref name) => format!("body {} (\"{}\")", i, name),
+                    None => format!("body {}", i),
+                };
+                SaveError::Scenario(format!("{}: {}", which, e))
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn export(&self, path: &Path) -> Result<(), SaveError> {
+        let text = self.to_string(ScenarioFormat::from_path(path)?)?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn import(path: &Path) -> Result<Scenario, SaveError> {
+        let format = ScenarioFormat::from_path(path)?;
+        let text = std::fs::read_to_string(path)?;
+        Self::from_str(&text, format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ggez::nalgebra::{Point2, Vector2};
+    use crate::{body::Body, gravity::GravitySolver, timestep::Timestep};
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.settings.gravity_solver = GravitySolver::barnes_hut();
+        world.settings.timestep = Timestep::adaptive();
+        world.settings.softening = 2.5;
+        world.add_body(Point2::new(0.0, 0.0), Vector2::new(0.0, 0.0), 30.0);
+        let moon = world.add_body(Point2::new(-120.5, 40.25), Vector2::new(0.1, -3.0), 4.0);
+        world.rename_body(moon, "moon".to_owned());
+        world.bodies()[&moon].borrow_mut().softening = Some(1.0);
+        world
+    }
+
+    #[test]
+    fn round_trips() {
+        let scenario = Scenario::from_world(&world());
+        for &format in [ScenarioFormat::Json, ScenarioFormat::Ron].iter() {
+            let text = scenario.to_string(format).unwrap();
+            let loaded = Scenario::from_str(&text, format).unwrap();
+
+            assert_eq!(loaded.to_string(format).unwrap(), text, "{:?}", format);
+            assert_eq!(loaded.settings.gravity_solver, GravitySolver::barnes_hut());
+            assert_eq!(loaded.bodies[1].name.as_deref(), Some("moon"));
+            assert_eq!(loaded.bodies[1].pos_x, -120.5);
+            assert_eq!(loaded.bodies[1].softening, Some(1.0));
+        }
+    }
+
+    fn rejection(text: &str, format: ScenarioFormat) -> String {
+        match Scenario::from_str(text, format) {
+            Err(SaveError::Scenario(e)) => e,
+            Err(e) => panic!("wrong error: {}", e),
+            Ok(_) => panic!("{} was accepted", text),
+        }
+    }
+
+    #[test]
+    fn rejects_bad_bodies() {
+        let e = rejection(
+            r#"{"bodies": [{"pos_x": 0, "pos_y": 0, "radius": 1}, {"name": "rock", "pos_x": 0, "pos_y": 0, "radius": -2}]}"#,
+            ScenarioFormat::Json,
+        );
+        assert!(e.starts_with("body 1 (\"rock\"): radius must be more than 0"), "{}", e);
+
+        let e = rejection("(bodies: [(pos_x: NaN, pos_y: 0, radius: 1)])", ScenarioFormat::Ron);
+        assert!(e.starts_with("body 0: ") && e.contains("finite"), "{}", e);
+
+        let e = rejection("(bodies: [(pos_x: 0, pos_y: 0, radius: 1, vel_x: inf)])", ScenarioFormat::Ron);
+        assert!(e.starts_with("body 0: ") && e.contains("finite"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_bad_settings() {
+        for theta in ["0", "-0.5"].iter() {
+            let text = format!(r#"{{"settings": {{"gravity_solver": {{"BarnesHut": {{"theta": {}}}}}}}, "bodies": []}}"#, theta);
+            let e = rejection(&text, ScenarioFormat::Json);
+            assert!(e.starts_with("settings: theta must be more than 0"), "{}", e);
+        }
+
+        let e = rejection("(settings: (gravity_solver: BarnesHut(theta: NaN)), bodies: [])", ScenarioFormat::Ron);
+        assert!(e.starts_with("settings: theta must be more than 0"), "{}", e);
+    }
+
+    #[test]
+    fn missing_mass_comes_from_radius() {
+        let scenario = Scenario::from_str(
+            r#"{"bodies": [{"pos_x": 0, "pos_y": 0, "radius": 10}, {"pos_x": 50, "pos_y": 0, "radius": 10, "mass": 3}]}"#,
+            ScenarioFormat::Json,
+        )
+        .unwrap();
+        let bodies: Vec<Body> = scenario.bodies.iter().map(Body::from).collect();
+
+        let expected = Body::new(0, Point2::new(0.0, 0.0), Vector2::new(0.0, 0.0), 10.0, 0.0).mass;
+        assert!(expected > 0.0);
+        assert_eq!(bodies[0].mass, expected);
+        assert_eq!(bodies[1].mass, 3.0);
+    }
+}
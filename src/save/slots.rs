@@ -0,0 +1,157 @@
+// Named saves, one file each in the user data directory. ggez only mounts that directory read only,
+// so this goes through std::fs with the real path.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::{SaveError, SaveState};
+use crate::tools;
+
+const SLOT_DIR: &str = "saves";
+const SLOT_EXTENSION: &str = "bin";
+const MAX_NAME_LEN: usize = 40;
+// Before slots there was one save, `/save.bin` in ggez's writable (config) directory. It's shown as a
+// slot with a name no real slot can have, so old saves can still be loaded.
+const LEGACY_FILE: &str = "save.bin";
+pub const LEGACY_NAME: &str = "save.bin (legacy)";
+
+// What the save browser shows for each slot.
+pub struct SlotInfo {
+    pub name: String,
+    pub bodies: usize,
+    pub sim_time: f64,
+    pub created: u64,   // Seconds since the unix epoch
+    pub error: Option<String>,  // Set if the file couldn't be read, so it can still be deleted
+}
+
+impl SlotInfo {
+    pub fn summary(&self) -> String {
+        match self.error {
+            Some(ref e) => format!("{}  -  unreadable: {}", self.name, e),
+            None => format!(
+                "{}  -  {} bodies, t = {:.1}s, saved {}",
+                self.name, self.bodies, self.sim_time, format_age(self.created)
+            ),
+        }
+    }
+}
+
+pub struct SaveSlots {
+    dir: PathBuf,
+    legacy: PathBuf,
+}
+
+impl SaveSlots {
+    pub fn new(user_data_dir: &Path, user_config_dir: &Path) -> SaveSlots {
+        SaveSlots {
+            dir: user_data_dir.join(SLOT_DIR),
+            legacy: user_config_dir.join(LEGACY_FILE),
+        }
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.path_of(name).is_ok_and(|p| p.is_file())
+    }
+
+    pub fn save(&self, name: &str, state: &SaveState) -> Result<(), SaveError> {
+        let path = self.path_of(name)?;
+        fs::create_dir_all(&self.dir)?;
+        state.save_to_file(&path)
+    }
+
+    pub fn load(&self, name: &str) -> Result<SaveState, SaveError> {
+        let path = self.path_of(name)?;
+        if !path.is_file() {
+            return Err(SaveError::NoSuchSlot(name.to_owned()));
+        }
+        SaveState::load_from_file(&path)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), SaveError> {
+        let path = self.path_of(name)?;
+        if !path.is_file() {
+            return Err(SaveError::NoSuchSlot(name.to_owned()));
+        }
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    // Newest first, undated ones (like most legacy saves) last. Every save gets decoded for its
+    // metadata, which is fine for the sizes we deal with.
+    pub fn list(&self) -> Vec<SlotInfo> {
+        let mut slots: Vec<SlotInfo> = fs::read_dir(&self.dir)
+            .into_iter()    // Nothing there if nothing has been saved yet
+            .flatten()
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == SLOT_EXTENSION))
+            .filter_map(|p| {
+                let name = p.file_stem()?.to_str()?.to_owned();
+                Some(Self::slot_info(name, &p))
+            })
+            .collect();
+
+        if self.legacy.is_file() {
+            slots.push(Self::slot_info(LEGACY_NAME.to_owned(), &self.legacy));
+        }
+
+        slots.sort_by(|a, b| b.created.cmp(&a.created).then_with(|| a.name.cmp(&b.name)));
+        slots
+    }
+
+    fn slot_info(name: String, path: &Path) -> SlotInfo {
+        let decoded = fs::read(path)
+            .map_err(SaveError::from)
+            .and_then(|data| SaveState::decode(&data));
+
+        match decoded {
+            Ok((header, save)) => SlotInfo {
+                name,
+                bodies: save.planets.len(),
+                sim_time: save.world.time,
+                created: header.created,
+                error: None,
+            },
+            Err(e) => SlotInfo {
+                name,
+                bodies: 0,
+                sim_time: 0.0,
+                created: 0,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+
+    fn path_of(&self, name: &str) -> Result<PathBuf, SaveError> {
+        if name == LEGACY_NAME {
+            return Ok(self.legacy.clone());
+        }
+        validate_name(name)?;
+        Ok(self.dir.join(format!("{}.{}", name, SLOT_EXTENSION)))
+    }
+}
+
+// Names end up as file names, so keep them to things every OS is happy with.
+fn validate_name(name: &str) -> Result<(), SaveError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.trim() == name
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ' || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(SaveError::BadSlotName(name.to_owned()))
+    }
+}
+
+fn format_age(created: u64) -> String {
+    let age = tools::unix_time().saturating_sub(created);
+
+    match age {
+        _ if created == 0 => "at an unknown time".to_owned(),
+        0..=59 => "just now".to_owned(),
+        60..=3599 => format!("{} min ago", age / 60),
+        3600..=86_399 => format!("{} h ago", age / 3600),
+        _ => format!("{} days ago", age / 86_400),
+    }
+}
@@ -0,0 +1,134 @@
+use ggez::graphics::{self, DrawMode, DrawParam, Mesh, Rect, Text};
+use ggez::input::keyboard::KeyCode;
+use ggez::nalgebra as na;
+use ggez::{Context, GameResult};
+use na::Point2;
+
+use crate::save::slots::SlotInfo;
+
+const LINE_HEIGHT: f32 = 20.0;
+const PANEL_WIDTH: f32 = 600.0;
+const MAX_VISIBLE: usize = 20;
+
+// Things that need asking about first.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Confirm {
+    Delete(String),
+    Overwrite(String),
+}
+
+// What the browser wants MainState to do, after any confirmation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BrowserAction {
+    Load(String),
+    Delete(String),
+    Overwrite(String),
+}
+
+// List of save slots. Up/Down to pick, Return to load, S to overwrite, Delete to delete.
+#[derive(Default)]
+pub struct SaveBrowser {
+    pub open: bool,
+    slots: Vec<SlotInfo>,
+    cursor: usize,
+    confirm: Option<Confirm>,
+}
+
+impl SaveBrowser {
+    pub fn open(&mut self, slots: Vec<SlotInfo>) {
+        self.open = true;
+        self.confirm = None;
+        self.set_slots(slots);
+    }
+
+    // Opens straight onto a question, e.g when saving over an existing slot from the console.
+    pub fn open_with_confirm(&mut self, slots: Vec<SlotInfo>, confirm: Confirm) {
+        self.open(slots);
+        let (Confirm::Overwrite(ref name) | Confirm::Delete(ref name)) = confirm;
+        if let Some(i) = self.slots.iter().position(|s| s.name == *name) {
+            self.cursor = i;
+        }
+        self.confirm = Some(confirm);
+    }
+
+    #[inline]
+    pub fn close(&mut self) {
+        self.open = false;
+        self.confirm = None;
+    }
+
+    pub fn set_slots(&mut self, slots: Vec<SlotInfo>) {
+        self.slots = slots;
+        self.cursor = self.cursor.min(self.slots.len().saturating_sub(1));
+    }
+
+    pub fn key_down(&mut self, key: KeyCode) -> Option<BrowserAction> {
+        if let Some(confirm) = self.confirm.take() {
+            return match key {
+                KeyCode::Y | KeyCode::Return | KeyCode::NumpadEnter => Some(match confirm {
+                    Confirm::Delete(name) => BrowserAction::Delete(name),
+                    Confirm::Overwrite(name) => BrowserAction::Overwrite(name),
+                }),
+                KeyCode::N | KeyCode::Escape => None,
+                _ => {
+                    self.confirm = Some(confirm);  // Keep asking
+                    None
+                },
+            };
+        }
+
+        let selected = self.slots.get(self.cursor).map(|s| s.name.clone());
+        match key {
+            KeyCode::Up => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down => self.cursor = (self.cursor + 1).min(self.slots.len().saturating_sub(1)),
+            KeyCode::Escape | KeyCode::B => self.close(),
+            KeyCode::Return | KeyCode::NumpadEnter => return selected.map(BrowserAction::Load),
+            KeyCode::Delete => self.confirm = selected.map(Confirm::Delete),
+            KeyCode::S => self.confirm = selected.map(Confirm::Overwrite),
+            _ => (),
+        }
+        None
+    }
+
+    pub fn draw(&self, ctx: &mut Context, screen: Rect) -> GameResult {
+        let lines = MAX_VISIBLE.min(self.slots.len()).max(1) + 3;
+        let panel = Rect::new(
+            (screen.w - PANEL_WIDTH) / 2.0,
+            60.0,
+            PANEL_WIDTH,
+            lines as f32 * LINE_HEIGHT + 20.0,
+        );
+        let background = Mesh::new_rectangle(ctx, DrawMode::fill(), panel, [0.0, 0.0, 0.0, 0.8].into())?;
+        graphics::draw(ctx, &background, DrawParam::default())?;
+
+        let mut y = panel.y + 10.0;
+        let mut line = |ctx: &mut Context, s: &str, colour: [f32; 4]| -> GameResult {
+            let text = Text::new(s);
+            graphics::draw(ctx, &text, DrawParam::default().dest(Point2::new(panel.x + 10.0, y)).color(colour.into()))?;
+            y += LINE_HEIGHT;
+            Ok(())
+        };
+
+        line(ctx, "Saves (Return: load, S: overwrite, Delete: delete, Esc: close)", [1.0, 1.0, 1.0, 1.0])?;
+
+        if self.slots.is_empty() {
+            line(ctx, "Nothing saved yet. Use \"save <name>\" in the console (Return).", [0.6, 0.6, 0.6, 1.0])?;
+        }
+
+        // Scroll so the cursor stays in view
+        let first = self.cursor.saturating_sub(MAX_VISIBLE - 1);
+        for (i, slot) in self.slots.iter().enumerate().skip(first).take(MAX_VISIBLE) {
+            let colour = if i == self.cursor { [1.0, 0.85, 0.3, 1.0] } else { [0.8, 0.8, 0.8, 1.0] };
+            let marker = if i == self.cursor { "> " } else { "  " };
+            line(ctx, &format!("{}{}", marker, slot.summary()), colour)?;
+        }
+
+        match self.confirm {
+            Some(Confirm::Delete(ref name)) => line(ctx, &format!("Delete \"{}\"? (Y/N)", name), [1.0, 0.4, 0.4, 1.0])?,
+            Some(Confirm::Overwrite(ref name)) => line(ctx, &format!("Overwrite \"{}\"? (Y/N)", name), [1.0, 0.4, 0.4, 1.0])?,
+            None => (),
+        }
+
+        Ok(())
+    }
+}
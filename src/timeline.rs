@@ -0,0 +1,226 @@
+use ggez::graphics::{self, DrawMode, DrawParam, Mesh, Rect};
+use ggez::{Context, GameResult};
+
+use std::collections::VecDeque;
+
+use crate::save::{SaveError, SaveState};
+
+pub const KEYFRAME_INTERVAL: f64 = 0.5;    // Seconds of sim time
+pub const DEFAULT_BUDGET_MB: usize = 64;
+const BAR_HEIGHT: f32 = 4.0;
+
+struct Keyframe {
+    time: f64,
+    data: Vec<u8>,  // Encoded `SaveState`, a lot smaller than keeping the structs around
+}
+
+// Recording of the simulation as keyframes every KEYFRAME_INTERVAL, oldest thrown away once over
+// budget. While paused it can be scrubbed through, and carrying on from a keyframe branches off a
+// new run, dropping everything after it.
+pub struct Timeline {
+    keyframes: VecDeque<Keyframe>,
+    bytes: usize,
+    budget: usize,  // Bytes
+    cursor: Option<usize>,  // Keyframe being looked at when scrubbing
+}
+
+impl Timeline {
+    pub fn new(budget_mb: usize) -> Timeline {
+        Timeline {
+            keyframes: VecDeque::new(),
+            bytes: 0,
+            budget: budget_mb * 1024 * 1024,
+            cursor: None,
+        }
+    }
+
+    #[inline]
+    pub fn is_scrubbing(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    #[inline]
+    pub fn keyframe_count(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    #[inline]
+    pub fn megabytes(&self) -> f64 {
+        self.bytes as f64 / (1024.0 * 1024.0)
+    }
+
+    #[inline]
+    pub fn budget_mb(&self) -> usize {
+        self.budget / (1024 * 1024)
+    }
+
+    pub fn set_budget_mb(&mut self, budget_mb: usize) {
+        self.budget = budget_mb * 1024 * 1024;
+        self.evict();
+    }
+
+    // Also true if time has gone backwards (e.g from an undo), `record` sorts that out.
+    pub fn due(&self, time: f64) -> bool {
+        !self.is_scrubbing() && self.keyframes.back().is_none_or(|k| time >= k.time + KEYFRAME_INTERVAL || time < k.time)
+    }
+
+    pub fn record(&mut self, time: f64, state: &SaveState) -> Result<(), SaveError> {
+        let data = state.encode()?;
+
+        // Anything at or after this time belongs to a run that no longer happened.
+        while self.keyframes.back().is_some_and(|k| k.time >= time) {
+            self.pop_back();
+        }
+        self.bytes += data.len();
+        self.keyframes.push_back(Keyframe { time, data });
+        self.evict();
+
+        Ok(())
+    }
+
+    // Moves the cursor by `by` keyframes and returns the state there. Starts from the newest keyframe
+    // if not already scrubbing, so record the present first to be able to come back to it.
+    pub fn scrub(&mut self, by: isize) -> Option<Result<SaveState, SaveError>> {
+        let last = self.keyframes.len().checked_sub(1)?;
+        let current = self.cursor.unwrap_or(last) as isize;
+        let target = (current + by).clamp(0, last as isize) as usize;
+
+        if self.cursor == Some(target) {
+            return None;
+        }
+        self.cursor = Some(target);
+
+        Some(SaveState::decode(&self.keyframes[target].data).map(|(_, state)| state))
+    }
+
+    // Carry on from wherever the cursor is, forgetting what came after it.
+    pub fn branch(&mut self) {
+        if let Some(cursor) = self.cursor.take() {
+            while self.keyframes.len() > cursor + 1 {
+                self.pop_back();
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.bytes = 0;
+        self.cursor = None;
+    }
+
+    // Always keeps at least one keyframe, so there is somewhere to go back to.
+    fn evict(&mut self) {
+        while self.bytes > self.budget && self.keyframes.len() > 1 {
+            if let Some(k) = self.keyframes.pop_front() {
+                self.bytes -= k.data.len();
+            }
+            if let Some(cursor) = self.cursor.as_mut() {
+                *cursor = cursor.saturating_sub(1);
+            }
+        }
+    }
+
+    fn pop_back(&mut self) {
+        if let Some(k) = self.keyframes.pop_back() {
+            self.bytes -= k.data.len();
+        }
+    }
+
+    // Bar along the bottom of the screen covering the recorded time, with a marker for `time`.
+    pub fn draw(&self, ctx: &mut Context, screen: Rect, time: f64) -> GameResult {
+        let (start, end) = match (self.keyframes.front(), self.keyframes.back()) {
+            (Some(first), Some(last)) => (first.time, last.time.max(time)),
+            _ => return Ok(()),
+        };
+        if end <= start {
+            return Ok(());
+        }
+
+        let y = screen.h - BAR_HEIGHT;
+        let background = Mesh::new_rectangle(
+            ctx,
+            DrawMode::fill(),
+            Rect::new(0.0, y, screen.w, BAR_HEIGHT),
+            [0.3, 0.3, 0.3, 0.8].into(),
+        )?;
+        graphics::draw(ctx, &background, DrawParam::default())?;
+
+        let x = ((time - start) / (end - start)) as f32 * screen.w;
+        let colour = if self.is_scrubbing() { [1.0, 0.85, 0.3, 1.0] } else { [0.3, 0.8, 1.0, 1.0] };
+        let marker = Mesh::new_rectangle(
+            ctx,
+            DrawMode::fill(),
+            Rect::new(x - 2.0, y - BAR_HEIGHT, 4.0, BAR_HEIGHT * 2.0),
+            colour.into(),
+        )?;
+        graphics::draw(ctx, &marker, DrawParam::default())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::WorldSaveData;
+
+    fn state(time: f64) -> SaveState {
+        SaveState { world: WorldSaveData { time, ..Default::default() }, ..Default::default() }
+    }
+
+    // Keyframes at 0, 0.5, 1.0 ... up to (but not including) `count` intervals
+    fn recorded(count: usize) -> Timeline {
+        let mut timeline = Timeline::new(DEFAULT_BUDGET_MB);
+        for i in 0..count {
+            let time = i as f64 * KEYFRAME_INTERVAL;
+            assert!(timeline.due(time));
+            timeline.record(time, &state(time)).unwrap();
+        }
+        timeline
+    }
+
+    fn scrub_time(timeline: &mut Timeline, by: isize) -> f64 {
+        timeline.scrub(by).unwrap().unwrap().world.time
+    }
+
+    #[test]
+    fn eviction_keeps_a_keyframe_and_the_cursor() {
+        let mut timeline = recorded(10);
+        let size = timeline.bytes / 10;
+        assert_eq!(scrub_time(&mut timeline, -2), 7.0 * KEYFRAME_INTERVAL);
+
+        // Room for the newest 5, so the cursor moves down to where keyframe 7 ended up
+        timeline.budget = size * 5;
+        timeline.evict();
+        assert_eq!(timeline.keyframe_count(), 5);
+        assert_eq!(scrub_time(&mut timeline, -1), 6.0 * KEYFRAME_INTERVAL);
+
+        timeline.set_budget_mb(0);
+        assert_eq!(timeline.keyframe_count(), 1);
+        assert_eq!(timeline.bytes, size);
+        assert!(timeline.scrub(-1).is_none());
+    }
+
+    #[test]
+    fn branching_drops_later_keyframes() {
+        let mut timeline = recorded(10);
+        assert_eq!(scrub_time(&mut timeline, -4), 5.0 * KEYFRAME_INTERVAL);
+
+        timeline.branch();
+        assert!(!timeline.is_scrubbing());
+        assert_eq!(timeline.keyframe_count(), 6);
+        assert_eq!(scrub_time(&mut timeline, 0), 5.0 * KEYFRAME_INTERVAL);
+    }
+
+    #[test]
+    fn recording_in_the_past_truncates_the_future() {
+        // e.g after undoing back to t = 1.1
+        let mut timeline = recorded(10);
+        assert!(timeline.due(1.1));
+        timeline.record(1.1, &state(1.1)).unwrap();
+
+        assert_eq!(timeline.keyframe_count(), 4);
+        assert_eq!(scrub_time(&mut timeline, 0), 1.1);
+        assert_eq!(scrub_time(&mut timeline, -1), 1.0);
+    }
+}
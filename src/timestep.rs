@@ -0,0 +1,177 @@
+use ggez::nalgebra as na;
+use na::Vector2;
+use serde::{Serialize, Deserialize};
+
+// Fraction of the shortest encounter time taken per substep. Lower is more accurate.
+pub const DEFAULT_TOLERANCE: f64 = 0.01;
+// Most substeps one physics step can be split into, so a near head on collision can't stall the sim.
+pub const MAX_SUBSTEPS: u32 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum Timestep {
+    #[default]
+    Fixed,                          // Every physics step is one step of the integrator.
+    Adaptive { tolerance: f64 },    // Physics steps are split up during close encounters.
+}
+
+impl Timestep {
+    #[inline]
+    pub fn adaptive() -> Timestep {
+        Timestep::Adaptive { tolerance: DEFAULT_TOLERANCE }
+    }
+
+    /* Longest step the integrator should take from this state, or None if any step is fine.
+
+       For every body this is how long it would take to fall its own size from rest, sqrt(L / |a|),
+       times the tolerance. L is its radius plus its softening length, and a is its acceleration from
+       the solver's last force evaluation, so this is O(N) and keeps Barnes-Hut fast. Bodies pull
+       harder the closer they get, so steps get shorter during close encounters.
+    */
+    pub fn max_dt(&self, acc: &[Vector2<f64>], lengths: &[f64]) -> Option<f64> {
+        let tolerance = match *self {
+            Timestep::Fixed => return None,
+            Timestep::Adaptive { tolerance } => tolerance,
+        };
+
+        let shortest_sq = acc
+            .iter()
+            .zip(lengths.iter())
+            .map(|(a, l)| l / a.norm())
+            .fold(f64::INFINITY, f64::min);
+
+        if shortest_sq.is_finite() {
+            Some(tolerance * shortest_sq.sqrt())
+        } else {
+            None
+        }
+    }
+
+    pub fn toggled(&self) -> Timestep {
+        match *self {
+            Timestep::Fixed => Self::adaptive(),
+            Timestep::Adaptive { .. } => Timestep::Fixed,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match *self {
+            Timestep::Fixed => "Fixed".to_owned(),
+            Timestep::Adaptive { tolerance } => format!("Adaptive (tolerance {})", tolerance),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Point2;
+    use std::f64::consts::PI;
+    use crate::{body::Body, diagnostics::Diagnostics, world::World, GRAV_CONSTANT};
+
+    #[test]
+    fn fixed_never_limits() {
+        assert_eq!(Timestep::Fixed.max_dt(&[Vector2::new(1e9, 0.0)], &[1.0]), None);
+    }
+
+    #[test]
+    fn tolerance_is_respected() {
+        // Fall times are sqrt(20 / 5) = 2 and sqrt(1 / 0.5) = sqrt(2), the shortest one counts
+        let acc = [Vector2::new(3.0, 4.0), Vector2::new(0.0, 0.5)];
+        let lengths = [20.0, 1.0];
+        for &tolerance in [0.001, 0.01, 0.5].iter() {
+            let max = Timestep::Adaptive { tolerance }.max_dt(&acc, &lengths).unwrap();
+            assert!((max - tolerance * 2.0f64.sqrt()).abs() < 1e-12, "tolerance {} gave {}", tolerance, max);
+        }
+        // Nothing pulling on anything
+        assert_eq!(Timestep::adaptive().max_dt(&[Vector2::new(0.0, 0.0)], &[1.0]), None);
+    }
+
+    #[test]
+    fn substeps_are_clamped() {
+        let dt = 1.0 / 120.0;
+        let shortest = |tolerance| {
+            let mut world = World::new();
+            world.settings.timestep = Timestep::Adaptive { tolerance };
+            world.add_body(Point2::new(0.0, 0.0), Vector2::new(0.0, 0.0), 20.0);
+            world.add_body(Point2::new(50.0, 0.0), Vector2::new(0.0, 0.0), 5.0);
+            world.step(dt);
+            world.last_substep()
+        };
+
+        // Wants far shorter steps than allowed
+        let (substep, count) = shortest(1e-12);
+        assert!((substep - dt / MAX_SUBSTEPS as f64).abs() < 1e-15, "shortest substep {}", substep);
+        assert_eq!(count, MAX_SUBSTEPS);
+
+        // Happy with far longer steps than the physics step
+        assert_eq!(shortest(1e6), (dt, 1));
+    }
+
+    // A light planet going round a heavy sun, from apoapsis.
+    fn eccentric_orbit(timestep: Timestep) -> (World, f64, f64) {
+        const ECCENTRICITY: f64 = 0.9;
+        const APOAPSIS: f64 = 1000.0;
+        const PERIOD: f64 = 10.0;
+
+        let semi_major = APOAPSIS / (1.0 + ECCENTRICITY);
+        let gm = 4.0 * PI * PI * semi_major.powi(3) / (PERIOD * PERIOD);
+        let speed = (gm * (1.0 - ECCENTRICITY) / APOAPSIS).sqrt();
+
+        let mut world = World::new();
+        world.settings.timestep = timestep;
+        world.add_existing_body(Body::new(0, Point2::new(0.0, 0.0), Vector2::new(0.0, 0.0), 5.0, gm / GRAV_CONSTANT));
+        world.add_existing_body(Body::new(0, Point2::new(APOAPSIS, 0.0), Vector2::new(0.0, speed), 1.0, 1.0));
+        (world, PERIOD, semi_major * (1.0 - ECCENTRICITY))
+    }
+
+    // Worst relative energy error over `steps` steps of `dt`
+    fn energy_error(world: &mut World, steps: usize, dt: f64) -> f64 {
+        let start = Diagnostics::measure(world).total_energy();
+        let mut worst: f64 = 0.0;
+        for _ in 0..steps {
+            world.step(dt);
+            let energy = Diagnostics::measure(world).total_energy();
+            worst = worst.max(((energy - start) / start).abs());
+        }
+        worst
+    }
+
+    #[test]
+    fn adaptive_beats_fixed_on_eccentric_orbit() {
+        const STEPS: usize = 200;
+
+        // One orbit with adaptive substeps, keeping track of how many were taken and where the
+        // most were needed
+        let (mut world, period, periapsis) = eccentric_orbit(Timestep::Adaptive { tolerance: 0.1 });
+        let dt = period / STEPS as f64;
+        let start = Diagnostics::measure(&world).total_energy();
+        let mut adaptive_error: f64 = 0.0;
+        let mut substeps = 0;
+        let mut busiest = (0, 0.0);
+        for _ in 0..STEPS {
+            world.step(dt);
+            let (_, count) = world.last_substep();
+            substeps += count as usize;
+
+            let bodies = world.bodies();
+            let distance = na::distance(&bodies[&0].borrow().pos, &bodies[&1].borrow().pos);
+            if count > busiest.0 {
+                busiest = (count, distance);
+            }
+            let energy = Diagnostics::measure(&world).total_energy();
+            adaptive_error = adaptive_error.max(((energy - start) / start).abs());
+        }
+
+        assert!(busiest.0 > 10, "at most {} substeps a step", busiest.0);
+        assert!(busiest.1 < periapsis * 1.5, "most substeps {} from the sun, periapsis is {}", busiest.1, periapsis);
+
+        // The same number of force evaluations, spread out evenly
+        let (mut world, _, _) = eccentric_orbit(Timestep::Fixed);
+        let fixed_error = energy_error(&mut world, substeps, period / substeps as f64);
+
+        assert!(
+            adaptive_error * 10.0 < fixed_error,
+            "adaptive {} vs fixed {} with {} steps", adaptive_error, fixed_error, substeps
+        );
+    }
+}
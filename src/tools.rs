@@ -9,17 +9,26 @@ pub fn distance_squared_to<T: RealField>(my_pos: &Point2<T>, other_pos: &Point2<
 }
 
 #[inline]
+#[allow(dead_code)]
 pub fn distance_to<T: RealField>(my_pos: &Point2<T>, other_pos: &Point2<T>) -> T {
     distance_squared_to(my_pos, other_pos).sqrt()
 }
 
+// Force on body 1 from body 2. `softening_sq` is the square of the Plummer softening length, which
+// stops the force blowing up as the bodies get close (see `gravity::pair_softening_sq`). 0 is plain Newton.
 #[inline]
-pub fn newtonian_grav(m1: f64, m2: f64, pos1: &Point2<f64>, pos2: &Point2<f64>) -> Vector2<f64> {
-    let dist_vec = Vector2::new(pos2.x - pos1.x, pos2.y - pos1.y);
-    let force = (GRAV_CONSTANT * m1 * m2) / (dist_vec.x.powi(2) + dist_vec.y.powi(2));
-    let angle = dist_vec.y.atan2(dist_vec.x);
+pub fn newtonian_grav(m1: f64, m2: f64, pos1: &Point2<f64>, pos2: &Point2<f64>, softening_sq: f64) -> Vector2<f64> {
+    let dist_vec = pos2 - pos1;
+    let softened_sq = dist_vec.norm_squared() + softening_sq;
 
-    Vector2::new(force * angle.cos(), force * angle.sin())
+    // G m1 m2 / (r^2 + e^2), in the direction of dist_vec
+    dist_vec * (GRAV_CONSTANT * m1 * m2 / (softened_sq * softened_sq.sqrt()))
+}
+
+// Potential energy of the pair, softened the same way as `newtonian_grav` so that the two agree.
+#[inline]
+pub fn newtonian_potential(m1: f64, m2: f64, pos1: &Point2<f64>, pos2: &Point2<f64>, softening_sq: f64) -> f64 {
+    -GRAV_CONSTANT * m1 * m2 / (distance_squared_to(pos1, pos2) + softening_sq).sqrt()
 }
 
 #[inline]
@@ -28,4 +37,17 @@ pub fn get_components<T: RealField>(magnitude: T, angle: T) -> Vector2<T> {
         magnitude * angle.cos(),
         magnitude * angle.sin()
     )
-}
\ No newline at end of file
+}
+
+// Time since the unix epoch, or 0 if the clock is set before it.
+pub fn since_epoch() -> std::time::Duration {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+// Seconds since the unix epoch, for naming files and dating saves.
+#[inline]
+pub fn unix_time() -> u64 {
+    since_epoch().as_secs()
+}
@@ -0,0 +1,709 @@
+use ggez::nalgebra as na;
+use na::{Point2, Vector2};
+
+use serde::{Serialize, Deserialize};
+
+use std::cell::{Ref, RefCell};
+use std::collections::BTreeMap;
+
+use crate::{
+    body::{Body, BodyID, Mobile},
+    collision,
+    gravity::{self, GravitySolver},
+    integrator::Integrator,
+    timestep::{self, Timestep},
+    tools,
+};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimSettings {
+    pub gravity_solver: GravitySolver,
+    pub integrator: Integrator,
+    pub timestep: Timestep,
+    pub softening: f64,     // Plummer softening length for bodies without their own
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct WorldSaveData {
+    pub id_counter: BodyID,
+    pub time: f64,
+    pub settings: SimSettings,
+    pub seed: u64,
+    pub collision_energy: f64,
+}
+
+// One body absorbing another in a collision.
+#[derive(Clone, Copy, Debug)]
+pub struct Merge {
+    pub survivor: BodyID,
+    pub absorbed: BodyID,
+}
+
+// Follows an id through a list of merges, so that anything keeping track of a body (camera, selection
+// etc) can carry on with whatever absorbed it.
+pub fn survivor_of(mut id: BodyID, merges: &[Merge]) -> BodyID {
+    for m in merges.iter() {
+        if m.absorbed == id {
+            id = m.survivor;
+        }
+    }
+    id
+}
+
+// The simulation itself, with no window or ggez context attached. Can be stepped with any dt, so it
+// can be driven from the game loop, a test or a batch job.
+pub struct World {
+    bodies: BTreeMap<BodyID, RefCell<Body>>, // Ordered by id, so everything is done in the same order every run
+    collided_bodies: Vec<Merge>,
+    id_counter: BodyID,
+    time: f64,  // Simulation time in seconds
+    seed: u64,  // Everything random comes from this, see `stream_seed`
+    last_substep: (f64, u32),   // Shortest substep and how many there were, in the last step
+    collision_energy: f64,  // Total energy taken out by merges, see `energy_lost_in_collisions`
+    acc_cache: AccelerationCache,
+    pub settings: SimSettings,
+}
+
+impl World {
+    pub fn new() -> World {
+        World {
+            bodies: BTreeMap::new(),
+            collided_bodies: Vec::with_capacity(20),
+            id_counter: 0,
+            time: 0.0,
+            seed: 0,
+            last_substep: (0.0, 0),
+            collision_energy: 0.0,
+            acc_cache: AccelerationCache::default(),
+            settings: SimSettings::default(),
+        }
+    }
+
+    // Puts a body in with the id it already has, replacing anything with that id. For loading saves,
+    // so the id counter is not touched (see `restore`).
+    pub fn insert_body(&mut self, mut body: Body) {
+        let name = body.name.take().unwrap_or_else(|| Body::auto_name(body.id));
+        body.name = Some(self.unique_name(name, body.id));
+        self.bodies.insert(body.id, RefCell::new(body));
+    }
+
+    #[inline]
+    pub fn add_body(&mut self, pos: Point2<f64>, vel: Vector2<f64>, radius: f64) -> BodyID {
+        self.add_existing_body(Body::new(self.id_counter, pos, vel, radius, 0.0))
+    }
+
+    // Gives the body a new id, and returns it. Bodies without a name get one.
+    pub fn add_existing_body(&mut self, mut body: Body) -> BodyID {
+        let id = self.id_counter;
+        body.id = id;
+        let name = body.name.take().unwrap_or_else(|| Body::auto_name(id));
+        body.name = Some(self.unique_name(name, id));
+
+        self.bodies.insert(id, RefCell::new(body));
+        self.id_counter = self.id_counter.wrapping_add(1);
+
+        id
+    }
+
+    #[inline]
+    pub fn bodies(&self) -> &BTreeMap<BodyID, RefCell<Body>> {
+        &self.bodies
+    }
+
+    // Shortest step the integrator took during the last step, and how many steps it was split into.
+    #[inline]
+    pub fn last_substep(&self) -> (f64, u32) {
+        self.last_substep
+    }
+
+    // Running total of energy taken out by merges (negative if they freed more binding energy than
+    // kinetic energy they lost). Saves from before the header start it from 0.
+    #[inline]
+    pub fn collision_energy(&self) -> f64 {
+        self.collision_energy
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[inline]
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    // A seed for one random thing (e.g a body's trail), made from the world's seed and `stream`.
+    // Each stream is independent of the others, so the order things ask for them in doesn't matter.
+    #[inline]
+    pub fn stream_seed(&self, stream: u64) -> u64 {
+        self.seed ^ stream.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    }
+
+    #[inline]
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    #[inline]
+    pub fn body_count(&self) -> usize {
+        self.bodies.len()
+    }
+
+    pub fn remove_body(&mut self, id: BodyID) -> Option<Body> {
+        self.bodies.remove(&id).map(|rc| rc.into_inner())
+    }
+
+    // Returns the name the body ended up with, see `unique_name`.
+    pub fn rename_body(&mut self, id: BodyID, name: String) -> Option<String> {
+        let name = self.unique_name(name, id);
+        let body = self.bodies.get(&id)?;
+        body.borrow_mut().name = Some(name.clone());
+        Some(name)
+    }
+
+    // Case insensitive. Names are unique, so there's only ever one.
+    pub fn find_by_name(&self, name: &str) -> Option<BodyID> {
+        self.bodies
+            .iter()
+            .find(|(_, b)| b.borrow().name.as_ref().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+            .map(|(id, _)| *id)
+    }
+
+    // `name`, or `name 2`, `name 3`... if a body other than `id` already has it (ignoring case), so that
+    // a name always picks out one body. Auto names can clash with ones given by hand or in a scenario.
+    fn unique_name(&self, name: String, id: BodyID) -> String {
+        let taken = |name: &str| self.find_by_name(name).is_some_and(|other| other != id);
+        if !taken(&name) {
+            return name;
+        }
+        (2..).map(|i| format!("{} {}", name, i)).find(|n| !taken(n)).unwrap()
+    }
+
+    // All bodies with a name containing `pattern` (case insensitive), sorted by id.
+    pub fn search_names(&self, pattern: &str) -> Vec<BodyID> {
+        let pattern = pattern.to_lowercase();
+        self.bodies
+            .iter()
+            .filter(|(_, b)| b.borrow().name.as_ref().is_some_and(|n| n.to_lowercase().contains(&pattern)))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    pub fn save_data(&self) -> WorldSaveData {
+        WorldSaveData {
+            id_counter: self.id_counter,
+            time: self.time,
+            settings: self.settings,
+            seed: self.seed,
+            collision_energy: self.collision_energy,
+        }
+    }
+
+    // Everything apart from the bodies themselves.
+    pub fn restore(&mut self, save: &WorldSaveData) {
+        self.id_counter = save.id_counter;
+        self.time = save.time;
+        self.settings = save.settings;
+        self.seed = save.seed;
+        self.collision_energy = save.collision_energy;
+        self.collided_bodies.clear();
+    }
+
+    // Back to an empty scene at time 0. Settings and the seed are kept.
+    pub fn clear(&mut self) {
+        self.bodies.clear();
+        self.collided_bodies.clear();
+        self.id_counter = 0;
+        self.time = 0.0;
+        self.collision_energy = 0.0;
+    }
+
+    // Advances the simulation by dt. Returns the merges that happened, the absorbed bodies have
+    // been removed.
+    pub fn step(&mut self, dt: f64) -> Vec<Merge> {
+        let before_collisions = self.collide_bodies();
+        let mut merges = self.remove_collided_bodies();
+        if !before_collisions.is_empty() {
+            self.collision_energy += self.energy_lost_in_collisions(&before_collisions);
+        }
+
+        let start: Vec<Point2<f64>> = self.bodies.values().map(|b| b.borrow().pos).collect();
+        self.integrate(dt);
+        self.time += dt;
+
+        // Catch anything that passed through something else during the step. Moving bodies back to
+        // where they touched and on again afterwards changes the energy too, so that counts as part
+        // of the collision.
+        let (before_sweep, survivors) = self.sweep_bodies(&start);
+        merges.extend(self.remove_collided_bodies());
+        for (id, remaining) in survivors {
+            let mut body = self.bodies[&id].borrow_mut();
+            let vel = *body.vel();
+            body.pos += vel * remaining * dt;
+        }
+        if !before_sweep.is_empty() {
+            self.collision_energy += self.energy_lost_in_collisions(&before_sweep);
+        }
+
+        merges
+    }
+
+    // Closest body to the point that the point is inside of. min_radius is for making tiny
+    // bodies easier to click on.
+    pub fn body_at(&self, point: &Point2<f64>, min_radius: f64) -> Option<BodyID> {
+        let mut closest: Option<(BodyID, f64)> = None;
+
+        for (id, body) in self.bodies.iter() {
+            let body = body.borrow();
+            let dist_sq = tools::distance_squared_to(point, &body.pos);
+            let rad = body.radius.max(min_radius);
+
+            if dist_sq <= rad * rad && closest.is_none_or(|(_, d)| dist_sq < d) {
+                closest = Some((*id, dist_sq));
+            }
+        }
+
+        closest.map(|(id, _)| id)
+    }
+
+    // The body pulling hardest on the given body.
+    pub fn dominant_attractor(&self, id: BodyID) -> Option<BodyID> {
+        let me = self.bodies.get(&id)?.borrow();
+        let mut strongest: Option<(BodyID, f64)> = None;
+
+        for (other_id, other) in self.bodies.iter() {
+            if *other_id == id {
+                continue;
+            }
+            let other = other.borrow();
+            let softening_sq = gravity::pair_softening_sq(self.softening_sq(&me), self.softening_sq(&other));
+            let force = tools::newtonian_grav(me.mass, other.mass, &me.pos, &other.pos, softening_sq).norm();
+
+            if strongest.is_none_or(|(_, f)| force > f) {
+                strongest = Some((*other_id, force));
+            }
+        }
+
+        strongest.map(|(id, _)| id)
+    }
+
+    // Mass weighted average position, using positions interpolated by alpha (see `Body::interpolated_pos`).
+    pub fn centre_of_mass(&self, alpha: f64) -> Option<Point2<f64>> {
+        let mut total_mass = 0.0;
+        let mut weighted = Vector2::new(0.0, 0.0);
+
+        for (_, body) in self.bodies.iter() {
+            let body = body.borrow();
+            total_mass += body.mass;
+            weighted += body.interpolated_pos(alpha).coords * body.mass;
+        }
+
+        if total_mass > 0.0 {
+            Some(Point2::from(weighted / total_mass))
+        } else {
+            None
+        }
+    }
+
+    // Square of the body's softening length, falling back on the global one.
+    #[inline]
+    pub fn softening_sq(&self, body: &Body) -> f64 {
+        body.softening.unwrap_or(self.settings.softening).powi(2)
+    }
+
+    #[inline]
+    pub fn potential_energy_between(&self, a: &Body, b: &Body) -> f64 {
+        let softening_sq = gravity::pair_softening_sq(self.softening_sq(a), self.softening_sq(b));
+        tools::newtonian_potential(a.mass, b.mass, &a.pos, &b.pos, softening_sq)
+    }
+
+    // Returns every body that collided, as it was before colliding. Only the pairs the broad phase
+    // finds are checked. A body that grows from a merge is checked with its new size next step.
+    fn collide_bodies(&mut self) -> BTreeMap<BodyID, Body> {
+        let keys: Vec<BodyID> = self.bodies.keys().cloned().collect();
+        let (positions, radii): (Vec<_>, Vec<_>) = self.bodies
+            .values()
+            .map(|b| {
+                let b = b.borrow();
+                (b.pos, b.radius)
+            })
+            .unzip();
+        let mut before = BTreeMap::new();
+
+        for (i, j) in collision::overlapping_pairs(&positions, &radii) {
+            if self.collided_bodies.iter().any(|m| m.absorbed == keys[i] || m.absorbed == keys[j]) {
+                continue;
+            }
+
+            let mut me = self.bodies[&keys[i]].borrow_mut();
+            let mut other = self.bodies[&keys[j]].borrow_mut();
+
+            if collision::is_colliding(&me.pos, &other.pos, me.radius, other.radius) {
+                before.entry(keys[i]).or_insert_with(|| me.clone());
+                before.entry(keys[j]).or_insert_with(|| other.clone());
+
+                if me.radius < other.radius {
+                    other.collide(&me);
+                    self.collided_bodies.push(Merge { survivor: keys[j], absorbed: keys[i] });
+                } else {
+                    me.collide(&other);
+                    self.collided_bodies.push(Merge { survivor: keys[i], absorbed: keys[j] });
+                }
+            }
+        }
+
+        before
+    }
+
+    /* Swept check for bodies that went through each other during the step, e.g a small fast body
+       fired at a planet, which the overlap check at the start of each step would miss. Bodies are
+       taken to move in straight lines from where they were (`start`, in id order) to where they are
+       now, and pairs are merged in order of when they touched, at where they were at that time.
+
+       Returns the bodies that collided as they were at the end of the step (before being moved back),
+       and for each survivor the fraction of the step it has left to move with its new velocity. Each
+       body only merges once per sweep, anything else it hits is caught next step.
+    */
+    fn sweep_bodies(&mut self, start: &[Point2<f64>]) -> (BTreeMap<BodyID, Body>, Vec<(BodyID, f64)>) {
+        let keys: Vec<BodyID> = self.bodies.keys().cloned().collect();
+        let (end, radii): (Vec<_>, Vec<_>) = self.bodies
+            .values()
+            .map(|b| {
+                let b = b.borrow();
+                (b.pos, b.radius)
+            })
+            .unzip();
+
+        let mut hits: Vec<(f64, usize, usize)> = collision::swept_pairs(start, &end, &radii)
+            .into_iter()
+            .filter_map(|(i, j)| {
+                collision::time_of_impact(&start[i], &end[i], radii[i], &start[j], &end[j], radii[j])
+                    .map(|toi| (toi, i, j))
+            })
+            .collect();
+        hits.sort_by(|a, b| a.0.total_cmp(&b.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+
+        let mut before = BTreeMap::new();
+        let mut survivors = Vec::new();
+        let mut merged = vec![false; keys.len()];
+        for (toi, i, j) in hits {
+            if merged[i] || merged[j] {
+                continue;
+            }
+            merged[i] = true;
+            merged[j] = true;
+
+            let mut me = self.bodies[&keys[i]].borrow_mut();
+            let mut other = self.bodies[&keys[j]].borrow_mut();
+            before.insert(keys[i], me.clone());
+            before.insert(keys[j], other.clone());
+            me.pos = start[i] + (end[i] - start[i]) * toi;
+            other.pos = start[j] + (end[j] - start[j]) * toi;
+
+            let survivor = if me.radius < other.radius {
+                other.collide(&me);
+                self.collided_bodies.push(Merge { survivor: keys[j], absorbed: keys[i] });
+                keys[j]
+            } else {
+                me.collide(&other);
+                self.collided_bodies.push(Merge { survivor: keys[i], absorbed: keys[j] });
+                keys[i]
+            };
+            survivors.push((survivor, 1.0 - toi));
+        }
+
+        (before, survivors)
+    }
+
+    // Merging loses kinetic energy, and gets rid of the binding energy between the two. The bodies
+    // that collided are compared before and after (with each other and with everything else), and
+    // the difference goes in `collision_energy`, so that total energy plus that stays constant.
+    fn energy_lost_in_collisions(&self, before: &BTreeMap<BodyID, Body>) -> f64 {
+        let after: Vec<Ref<Body>> = before.keys().filter_map(|id| self.bodies.get(id)).map(|b| b.borrow()).collect();
+        let others: Vec<Ref<Body>> = self.bodies
+            .iter()
+            .filter(|(id, _)| !before.contains_key(id))
+            .map(|(_, b)| b.borrow())
+            .collect();
+
+        let before: Vec<&Body> = before.values().collect();
+        let after: Vec<&Body> = after.iter().map(|b| &**b).collect();
+        let others: Vec<&Body> = others.iter().map(|b| &**b).collect();
+        self.group_energy(&before, &others) - self.group_energy(&after, &others)
+    }
+
+    // Kinetic energy of the group, plus potential energy within it and with `others`.
+    fn group_energy(&self, group: &[&Body], others: &[&Body]) -> f64 {
+        let mut energy = 0.0;
+        for (i, a) in group.iter().enumerate() {
+            energy += a.kinetic_energy();
+            for b in group[i + 1..].iter().chain(others.iter()) {
+                energy += self.potential_energy_between(a, b);
+            }
+        }
+        energy
+    }
+
+    // Bodies are copied out so that every force evaluation the integrator does sees the same
+    // snapshot of positions, rather than some bodies having already moved. With an adaptive timestep
+    // dt is split into substeps, see `Timestep::max_dt`.
+    fn integrate(&mut self, dt: f64) {
+        let mut positions = Vec::with_capacity(self.bodies.len());
+        let mut velocities = Vec::with_capacity(self.bodies.len());
+        let mut masses = Vec::with_capacity(self.bodies.len());
+        let mut softening_sq = Vec::with_capacity(self.bodies.len());
+        let mut lengths = Vec::with_capacity(self.bodies.len());
+        let ids: Vec<BodyID> = self.bodies.keys().cloned().collect();
+        for id in ids.iter() {
+            let body = self.bodies[id].borrow();
+            positions.push(body.pos);
+            velocities.push(*body.vel());
+            masses.push(body.mass);
+            softening_sq.push(self.softening_sq(&body));
+            lengths.push(body.radius + softening_sq.last().unwrap().sqrt());
+        }
+
+        let solver = self.settings.gravity_solver;
+        let timestep = self.settings.timestep;
+        let adaptive = timestep != Timestep::Fixed;
+        let min_substep = dt / timestep::MAX_SUBSTEPS as f64;
+        let mut remaining = dt;
+        self.last_substep = (dt, 0);
+
+        // Substeps are sized from the latest accelerations the integrator asked for. The ones at the
+        // start are usually the ones the last step ended with, so come from the cache.
+        let cache = &mut self.acc_cache;
+        let mut last_acc = if adaptive {
+            cache.accelerations(solver, &positions, &masses, &softening_sq)
+        } else {
+            Vec::new()
+        };
+
+        while remaining > 0.0 {
+            let substep = match timestep.max_dt(&last_acc, &lengths) {
+                Some(max) if max < remaining => max.max(min_substep).min(remaining),
+                _ => remaining,
+            };
+            // Don't leave a sliver at the end from rounding
+            let substep = if remaining - substep < min_substep * 1e-3 { remaining } else { substep };
+
+            self.settings.integrator.step(&mut positions, &mut velocities, substep, |pos| {
+                let acc = cache.accelerations(solver, pos, &masses, &softening_sq);
+                if adaptive {
+                    last_acc.clone_from(&acc);
+                }
+                acc
+            });
+
+            remaining -= substep;
+            self.last_substep.0 = self.last_substep.0.min(substep);
+            self.last_substep.1 += 1;
+        }
+
+        for (id, (pos, vel)) in ids.iter().zip(positions.into_iter().zip(velocities)) {
+            let mut body = self.bodies[id].borrow_mut();
+            body.prev_pos = body.pos;
+            body.pos = pos;
+            *body.vel_mut() = vel;
+        }
+    }
+
+    fn remove_collided_bodies(&mut self) -> Vec<Merge> {
+        let merges: Vec<Merge> = self.collided_bodies.drain(..).collect();
+        if !merges.is_empty() {
+            self.bodies.retain(|key, _| !merges.iter().any(|m| m.absorbed == *key));
+        }
+        merges
+    }
+}
+
+/* Accelerations from the last force evaluation, and everything they were worked out from. Leapfrog
+   and velocity Verlet start each step from the positions the last one ended at, so they only need one
+   force evaluation a step instead of two. Collisions, edits and settings changes all change what
+   goes in, so nothing has to remember to clear it.
+*/
+#[derive(Default)]
+struct AccelerationCache {
+    solver: Option<GravitySolver>,
+    positions: Vec<Point2<f64>>,
+    masses: Vec<f64>,
+    softening_sq: Vec<f64>,
+    acc: Vec<Vector2<f64>>,
+    evaluations: usize,     // Misses, i.e how many times the solver actually ran
+}
+
+impl AccelerationCache {
+    fn accelerations(&mut self, solver: GravitySolver, positions: &[Point2<f64>], masses: &[f64], softening_sq: &[f64]) -> Vec<Vector2<f64>> {
+        let hit = self.solver == Some(solver)
+            && self.positions == positions
+            && self.masses == masses
+            && self.softening_sq == softening_sq;
+
+        if !hit {
+            self.acc = solver.accelerations(positions, masses, softening_sq);
+            self.solver = Some(solver);
+            self.positions.clear();
+            self.positions.extend_from_slice(positions);
+            self.masses.clear();
+            self.masses.extend_from_slice(masses);
+            self.softening_sq.clear();
+            self.softening_sq.extend_from_slice(softening_sq);
+            self.evaluations += 1;
+        }
+        self.acc.clone()
+    }
+}
+
+impl Default for World {
+    fn default() -> World {
+        World::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use crate::{camera::Camera, clock::SimClock, save::SaveState};
+
+    #[test]
+    fn fast_projectile_merges() {
+        // Moves 250 px a step, from one side of the planet to clear of the other without ever
+        // overlapping it at the end of a step
+        let dt = 1.0 / 120.0;
+        let mut world = World::new();
+        let planet = world.add_body(Point2::new(0.0, 0.0), Vector2::new(0.0, 0.0), 20.0);
+        let projectile = world.add_body(Point2::new(-100.0, 3.0), Vector2::new(30000.0, 0.0), 2.0);
+
+        let merges = world.step(dt);
+        assert_eq!(merges.len(), 1);
+        assert_eq!(survivor_of(projectile, &merges), planet);
+        assert_eq!(world.body_count(), 1);
+        assert!(world.collision_energy() > 0.0);
+    }
+
+    #[test]
+    fn fast_projectile_can_miss() {
+        let dt = 1.0 / 120.0;
+        let mut world = World::new();
+        world.add_body(Point2::new(0.0, 0.0), Vector2::new(0.0, 0.0), 20.0);
+        world.add_body(Point2::new(-100.0, 30.0), Vector2::new(30000.0, 0.0), 2.0);
+
+        assert!(world.step(dt).is_empty());
+        assert_eq!(world.body_count(), 2);
+    }
+
+    fn name(world: &World, id: BodyID) -> String {
+        world.bodies()[&id].borrow().name.clone().unwrap()
+    }
+
+    #[test]
+    fn names_stay_unique() {
+        let mut world = World::new();
+        let alpha = world.add_body(Point2::new(0.0, 0.0), Vector2::new(0.0, 0.0), 1.0);
+        let beta = world.add_body(Point2::new(10.0, 0.0), Vector2::new(0.0, 0.0), 1.0);
+        assert_eq!(world.rename_body(beta, "gamma".to_owned()).unwrap(), "gamma");
+
+        // Would have been auto named Gamma
+        let gamma = world.add_body(Point2::new(20.0, 0.0), Vector2::new(0.0, 0.0), 1.0);
+        assert_eq!(name(&world, gamma), "Gamma 2");
+        assert_eq!(world.find_by_name("GAMMA"), Some(beta));
+
+        // e.g from a scenario with two bodies of the same name
+        let mut sun = Body::new(0, Point2::new(0.0, 0.0), Vector2::new(0.0, 0.0), 1.0, 0.0);
+        sun.name = Some("Sun".to_owned());
+        world.add_existing_body(sun.clone());
+        let second_sun = world.add_existing_body(sun);
+        assert_eq!(name(&world, second_sun), "Sun 2");
+
+        assert_eq!(world.rename_body(alpha, "sun".to_owned()).unwrap(), "sun 3");
+        assert_eq!(world.rename_body(alpha, "Sun 3".to_owned()).unwrap(), "Sun 3");
+        assert!(world.rename_body(100, "Sun".to_owned()).is_none());
+
+        // Replacing a body keeps its name
+        let replacement = world.remove_body(gamma).unwrap();
+        world.insert_body(replacement);
+        assert_eq!(name(&world, gamma), "Gamma 2");
+    }
+
+    fn evaluations(integrator: Integrator, steps: usize) -> (World, usize) {
+        let mut world = World::new();
+        world.settings.integrator = integrator;
+        world.add_body(Point2::new(0.0, 0.0), Vector2::new(0.0, 0.0), 20.0);
+        world.add_body(Point2::new(200.0, 0.0), Vector2::new(0.0, 20.0), 2.0);
+        for _ in 0..steps {
+            world.step(1.0 / 120.0);
+        }
+        let count = world.acc_cache.evaluations;
+        (world, count)
+    }
+
+    #[test]
+    fn kick_drift_kick_evaluates_once_a_step() {
+        // One more for the very first step
+        assert_eq!(evaluations(Integrator::Leapfrog, 10).1, 11);
+        assert_eq!(evaluations(Integrator::VelocityVerlet, 10).1, 11);
+        assert_eq!(evaluations(Integrator::Yoshida4, 10).1, 30);
+
+        // Moving a body by hand throws the cached accelerations away
+        let (mut world, before) = evaluations(Integrator::Leapfrog, 10);
+        world.bodies()[&1].borrow_mut().pos.x += 10.0;
+        world.step(1.0 / 120.0);
+        assert_eq!(world.acc_cache.evaluations, before + 2);
+    }
+
+    #[test]
+    fn cache_makes_no_difference() {
+        let (mut cached, _) = evaluations(Integrator::Leapfrog, 10);
+        let (mut fresh, _) = evaluations(Integrator::Leapfrog, 10);
+        for _ in 0..10 {
+            cached.step(1.0 / 120.0);
+            fresh.acc_cache = AccelerationCache::default();
+            fresh.step(1.0 / 120.0);
+        }
+        assert_eq!(encoded_state(&cached), encoded_state(&fresh));
+    }
+
+    // A crowded cluster that merges as it collapses, and a projectile that can only hit the
+    // planet it's aimed at through the sweep.
+    fn busy_scene(seed: u64, solver: GravitySolver) -> (World, BodyID) {
+        let mut world = World::new();
+        world.set_seed(seed);
+        world.settings.gravity_solver = solver;
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 0..80 {
+            let pos = Point2::new(rng.gen_range(-300.0, 300.0), rng.gen_range(-300.0, 300.0));
+            let vel = Vector2::new(rng.gen_range(-20.0, 20.0), rng.gen_range(-20.0, 20.0));
+            world.add_body(pos, vel, rng.gen_range(2.0, 8.0));
+        }
+        world.add_body(Point2::new(2000.0, 0.0), Vector2::new(0.0, 0.0), 20.0);
+        let projectile = world.add_body(Point2::new(1900.0, 3.0), Vector2::new(30000.0, 0.0), 2.0);
+        (world, projectile)
+    }
+
+    fn encoded_state(world: &World) -> Vec<u8> {
+        let save = SaveState::new(world, &SimClock::new(120.0, 8), &Camera::new(800.0, 600.0), &BTreeMap::new());
+        bincode::serialize(&save).unwrap()
+    }
+
+    #[test]
+    fn same_seed_same_result() {
+        let dt = 1.0 / 120.0;
+        for solver in [GravitySolver::DirectSum, GravitySolver::barnes_hut()].iter() {
+            let (mut a, projectile) = busy_scene(7, *solver);
+            let (mut b, _) = busy_scene(7, *solver);
+            let mut merges = 0;
+            for _ in 0..300 {
+                merges += a.step(dt).len();
+                b.step(dt);
+            }
+
+            assert!(merges > 1, "{:?} only had {} merges", solver, merges);
+            assert!(!a.bodies().contains_key(&projectile));
+            assert_eq!(encoded_state(&a), encoded_state(&b), "{:?} diverged", solver);
+        }
+    }
+}